@@ -1,35 +1,123 @@
 use crate::{
     common::CalfErr,
-    parser::{Parser, Stmt},
-    semantic,
+    cst::Cst,
+    host::Registry,
+    lexer::RawToken,
+    number::CalfNumber,
+    parser::{Expr, ExprId, Parser, Stmt},
+    semantic::{self, Interface},
 };
 use alloc::vec::Vec;
-use core::{fmt::Debug, str::FromStr};
+use core::ops::Index;
 
 #[derive(Debug)]
-/// Abstract Syntax Tree.
+/// Abstract Syntax Tree. Expressions are stored in an arena and referenced by [`ExprId`].
 pub struct Ast<T> {
-    pub statements: Vec<Stmt<T>>,
+    pub statements: Vec<Stmt>,
+    exprs: Vec<Expr<T>>,
+    parents: Vec<Option<ExprId>>,
 }
 
 impl<'a, T> Ast<T>
 where
-    T: FromStr + Debug + PartialEq,
-    <T as FromStr>::Err: Debug,
+    T: CalfNumber,
 {
-    pub fn build(code: &'a str) -> Result<Self, CalfErr> {
-        let mut ast = Self {
-            statements: Default::default(),
-        };
-        let mut parser = Parser::new(code);
-        loop {
-            let stmt = parser.scan_stmt()?;
-            ast.statements.push(stmt);
-            if parser.ended() {
-                break;
-            }
+    pub fn build(code: &'a str) -> Result<Self, Vec<CalfErr>> {
+        Self::build_with(code, &Registry::new())
+    }
+
+    /// Build the AST of a program that can call the functions of a host registry.
+    /// Returns all the syntax errors found, or the first semantic error.
+    pub fn build_with(code: &'a str, host: &Registry<T>) -> Result<Self, Vec<CalfErr>> {
+        let (ast, errors) = Self::parse(code);
+        if !errors.is_empty() {
+            return Err(errors);
         }
-        semantic::check(&ast.statements)?;
+        semantic::check(&ast, host).map_err(|err| vec![err])?;
         Ok(ast)
     }
+
+    /// Describe the interface of the program: its free variables, and the names it defines.
+    /// Fails with the first semantic error.
+    pub fn interface(&self) -> Result<Interface, CalfErr> {
+        self.interface_with(&Registry::new())
+    }
+
+    /// Describe the interface of a program that can call the functions of a host registry, that are not inputs.
+    pub fn interface_with(&self, host: &Registry<T>) -> Result<Interface, CalfErr> {
+        semantic::check(self, host)
+    }
+
+    /// Parse the code without checking it. The AST is returned even if there are syntax errors,
+    /// with [`Syntagma::Error`](crate::Syntagma::Error) expressions in place of the parts that couldn't be parsed.
+    pub fn parse(code: &'a str) -> (Self, Vec<CalfErr>) {
+        Self::from_parser(Parser::new(code))
+    }
+
+    /// Derive the AST from a concrete syntax tree, like [`Ast::parse`] does from the code.
+    pub fn from_cst(cst: &Cst) -> (Self, Vec<CalfErr>) {
+        Self::from_parser(Parser::from_raw(cst.raw_tokens()))
+    }
+
+    fn from_parser<'r, I>(mut parser: Parser<T, I>) -> (Self, Vec<CalfErr>)
+    where
+        I: Iterator<Item = RawToken<'r>>,
+    {
+        let mut statements = Vec::new();
+        while let Some(stmt) = parser.scan_stmt() {
+            statements.push(stmt);
+        }
+        let (exprs, errors) = parser.finish();
+        (Self::new(statements, exprs), errors)
+    }
+}
+
+impl<T> Ast<T> {
+    fn new(statements: Vec<Stmt>, exprs: Vec<Expr<T>>) -> Self {
+        let mut parents = vec![None; exprs.len()];
+        for (index, expr) in exprs.iter().enumerate() {
+            for child in expr.syn.children() {
+                parents[child.index()] = Some(ExprId::new(index));
+            }
+        }
+        Self {
+            statements,
+            exprs,
+            parents,
+        }
+    }
+
+    /// Get an expression.
+    pub fn expr(&self, id: ExprId) -> &Expr<T> {
+        &self.exprs[id.index()]
+    }
+
+    /// Iterate over all the expressions. Children always come before their parents.
+    pub fn exprs(&self) -> impl Iterator<Item = (ExprId, &Expr<T>)> {
+        self.exprs
+            .iter()
+            .enumerate()
+            .map(|(index, expr)| (ExprId::new(index), expr))
+    }
+
+    /// Documentation of a name, from the doc comments of its last assignment.
+    pub fn doc(&self, name: &str) -> Option<&str> {
+        self.statements.iter().rev().find_map(|stmt| match stmt {
+            Stmt::Assign { name: n, doc, .. } if n == name => Some(doc.as_deref()),
+            _ => None,
+        })?
+    }
+
+    /// Parent of an expression, or `None` if it's the root of a statement.
+    pub fn parent(&self, id: ExprId) -> Option<ExprId> {
+        self.parents[id.index()]
+    }
+}
+
+impl<T> Index<ExprId> for Ast<T> {
+    type Output = Expr<T>;
+
+    fn index(&self, id: ExprId) -> &Self::Output {
+        self.expr(id)
+    }
 }
@@ -0,0 +1,339 @@
+use crate::{
+    ast::Ast,
+    common::{CalfErr, ErrCode, Span},
+    host::Registry,
+    lexer::TokenKind,
+    number::CalfNumber,
+    parser::{ExprId, Stmt, Syntagma},
+};
+use alloc::{string::String, vec::Vec};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Bytecode instruction.
+pub enum Instr {
+    /// Push a constant from the pool.
+    Const(u32),
+    /// Push a global variable.
+    LoadGlobal(u32),
+    /// Pop a value and store it in a global variable.
+    StoreGlobal(u32),
+    /// Push a local variable (function parameter) of the current call frame.
+    LoadLocal(u32),
+    /// Push a function.
+    Function(u32),
+    /// Pop one operand and push the result.
+    Unary(TokenKind),
+    /// Pop two operands and push the result.
+    Binary(TokenKind),
+    /// Check the left operand of `&&` or `||`, without popping it. If it's a number that decides the result,
+    /// replace it by the result and jump to the end of the operation, skipping the right operand.
+    ShortCircuit(TokenKind, u32),
+    /// Pop an index and a vector, and push the indexed element(s).
+    Index,
+    /// Pop a number of values and push a vector.
+    Vector(u32),
+    /// Pop the step (if any) and the initial value, and push a range. The size is a constant.
+    Range { len: u32, step: bool },
+    /// Pop the end (if any), the start (if any) and a vector, and push the slice.
+    Slice { start: bool, end: bool },
+    /// Pop a number of indexes and a vector, and push the gathered elements.
+    Gather(u32),
+    /// Pop the condition of a ternary operator. If it's a number, jump to the "else" branch when false.
+    /// If it's a vector, both branches are executed and the result is selected element-wise.
+    Test(u32),
+    /// End of the "then" branch. Jump to the end of the ternary operator, unless both branches are executed.
+    Else(u32),
+    /// End of a ternary operator.
+    EndTernary,
+    /// Pop a number of arguments and a function, and call it.
+    Call(u32),
+    /// Pop a number of arguments and call a function of the host registry.
+    CallHost { function: u32, args: u32 },
+    /// Return from a function call.
+    Return,
+    /// Pop a value and keep it as the result of the program.
+    Result,
+    /// End of the program.
+    Halt,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Compiled function.
+pub struct Function {
+    /// Address of the first instruction.
+    pub entry: u32,
+    /// Number of parameters.
+    pub arity: u32,
+}
+
+#[derive(Debug)]
+/// Compiled program.
+pub struct Program<T> {
+    pub code: Vec<Instr>,
+    /// Span in the source code of each instruction.
+    pub spans: Vec<Span>,
+    pub constants: Vec<T>,
+    pub globals: Vec<String>,
+    pub functions: Vec<Function>,
+}
+
+impl<T> Program<T>
+where
+    T: CalfNumber,
+{
+    /// Compile a program into bytecode.
+    pub fn compile(ast: &Ast<T>) -> Result<Self, CalfErr> {
+        Self::compile_with(ast, &Registry::new())
+    }
+
+    /// Compile a program that can call the functions of a host registry.
+    /// The same registry must be used by the virtual machine that runs it.
+    pub fn compile_with(ast: &Ast<T>, host: &Registry<T>) -> Result<Self, CalfErr> {
+        let mut compiler = Compiler {
+            ast,
+            host,
+            program: Program {
+                code: Default::default(),
+                spans: Default::default(),
+                constants: Default::default(),
+                globals: Default::default(),
+                functions: Default::default(),
+            },
+            params: &[],
+            pending: Default::default(),
+        };
+        for stmt in ast.statements.iter() {
+            match stmt {
+                Stmt::Assign { name, value, .. } => {
+                    compiler.expr(*value)?;
+                    let global = compiler.global(name);
+                    compiler.emit(Instr::StoreGlobal(global), &ast[*value].span);
+                }
+                Stmt::Expr { expr, .. } => {
+                    compiler.expr(*expr)?;
+                    compiler.emit(Instr::Result, &ast[*expr].span);
+                }
+            }
+        }
+        compiler.emit(Instr::Halt, &Span::default());
+
+        // Compile function bodies after the main code
+        let mut next = 0;
+        while next < compiler.pending.len() {
+            let (params, body) = compiler.pending[next];
+            compiler.program.functions[next].entry = compiler.program.code.len() as u32;
+            compiler.params = params;
+            compiler.expr(body)?;
+            compiler.emit(Instr::Return, &ast[body].span);
+            next += 1;
+        }
+
+        Ok(compiler.program)
+    }
+
+    /// Find the slot of a global variable.
+    pub fn global(&self, name: &str) -> Option<usize> {
+        self.globals.iter().position(|g| g == name)
+    }
+}
+
+struct Compiler<'a, T> {
+    ast: &'a Ast<T>,
+    program: Program<T>,
+    host: &'a Registry<T>,
+    /// Parameters of the function being compiled.
+    params: &'a [String],
+    /// Functions pending to be compiled, in the same order as `program.functions`.
+    pending: Vec<(&'a [String], ExprId)>,
+}
+
+impl<'a, T> Compiler<'a, T>
+where
+    T: CalfNumber,
+{
+    fn expr(&mut self, id: ExprId) -> Result<(), CalfErr> {
+        let ast = self.ast;
+        let expr = &ast[id];
+        let span = &expr.span;
+        match &expr.syn {
+            Syntagma::Number(n) => {
+                let constant = self.constant(*n);
+                self.emit(Instr::Const(constant), span);
+            }
+            Syntagma::Identifier(id) => self.load(id, span),
+            Syntagma::Vector { values } => {
+                for value in values {
+                    self.expr(*value)?;
+                }
+                self.emit(Instr::Vector(values.len() as u32), span);
+            }
+            Syntagma::Range { init, len, step } => {
+                if len.to_index().is_none() {
+                    return Err(CalfErr {
+                        code: ErrCode::InvalidRange,
+                        message: "Range size must be a non negative integer".into(),
+                        span: span.clone(),
+                    });
+                }
+                self.expr(*init)?;
+                if let Some(step) = step {
+                    self.expr(*step)?;
+                }
+                let len = self.constant(*len);
+                self.emit(
+                    Instr::Range {
+                        len,
+                        step: step.is_some(),
+                    },
+                    span,
+                );
+            }
+            Syntagma::Group { expr } => self.expr(*expr)?,
+            Syntagma::Slice { vector, start, end } => {
+                self.expr(*vector)?;
+                if let Some(start) = start {
+                    self.expr(*start)?;
+                }
+                if let Some(end) = end {
+                    self.expr(*end)?;
+                }
+                self.emit(
+                    Instr::Slice {
+                        start: start.is_some(),
+                        end: end.is_some(),
+                    },
+                    span,
+                );
+            }
+            Syntagma::Gather { vector, indexes } => {
+                self.expr(*vector)?;
+                for index in indexes {
+                    self.expr(*index)?;
+                }
+                self.emit(Instr::Gather(indexes.len() as u32), span);
+            }
+            Syntagma::UnaryOp { op, child } => {
+                self.expr(*child)?;
+                self.emit(Instr::Unary(*op), span);
+            }
+            Syntagma::BinaryOp {
+                op,
+                left_child,
+                right_child,
+            } => {
+                self.expr(*left_child)?;
+                let short_circuit = match op {
+                    TokenKind::TwoAnds | TokenKind::TwoOrs => {
+                        Some(self.emit(Instr::ShortCircuit(*op, 0), span))
+                    }
+                    _ => None,
+                };
+                self.expr(*right_child)?;
+                if *op == TokenKind::Sharp {
+                    self.emit(Instr::Index, span);
+                } else {
+                    self.emit(Instr::Binary(*op), span);
+                }
+                if let Some(jump) = short_circuit {
+                    self.program.code[jump] =
+                        Instr::ShortCircuit(*op, self.program.code.len() as u32);
+                }
+            }
+            Syntagma::TernaryOp {
+                left_child,
+                mid_child,
+                right_child,
+            } => {
+                self.expr(*left_child)?;
+                let test = self.emit(Instr::Test(0), span);
+                self.expr(*mid_child)?;
+                let else_jump = self.emit(Instr::Else(0), span);
+                self.program.code[test] = Instr::Test(self.program.code.len() as u32);
+                self.expr(*right_child)?;
+                self.program.code[else_jump] = Instr::Else(self.program.code.len() as u32);
+                self.emit(Instr::EndTernary, span);
+            }
+            Syntagma::Call { func, args } => {
+                if let Some(function) = self.host_function(func) {
+                    for arg in args {
+                        self.expr(*arg)?;
+                    }
+                    self.emit(
+                        Instr::CallHost {
+                            function,
+                            args: args.len() as u32,
+                        },
+                        span,
+                    );
+                    return Ok(());
+                }
+                self.load(func, span);
+                for arg in args {
+                    self.expr(*arg)?;
+                }
+                self.emit(Instr::Call(args.len() as u32), span);
+            }
+            Syntagma::Lambda { params, body } => {
+                let function = self.program.functions.len() as u32;
+                self.program.functions.push(Function {
+                    entry: 0,
+                    arity: params.len() as u32,
+                });
+                self.pending.push((params, *body));
+                self.emit(Instr::Function(function), span);
+            }
+            Syntagma::Error => {
+                return Err(CalfErr {
+                    code: ErrCode::InvalidProgram,
+                    message: "Can't compile an expression with syntax errors".into(),
+                    span: span.clone(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Find a host function, unless the name is shadowed by a parameter or a global variable.
+    fn host_function(&self, id: &str) -> Option<u32> {
+        if self.params.iter().any(|p| p == id) || self.program.global(id).is_some() {
+            return None;
+        }
+        self.host.find(id).map(|function| function as u32)
+    }
+
+    fn load(&mut self, id: &str, span: &Span) {
+        if let Some(local) = self.params.iter().position(|p| p == id) {
+            self.emit(Instr::LoadLocal(local as u32), span);
+        } else {
+            let global = self.global(id);
+            self.emit(Instr::LoadGlobal(global), span);
+        }
+    }
+
+    fn global(&mut self, name: &str) -> u32 {
+        if let Some(global) = self.program.global(name) {
+            global as u32
+        } else {
+            self.program.globals.push(name.into());
+            (self.program.globals.len() - 1) as u32
+        }
+    }
+
+    fn constant(&mut self, n: T) -> u32 {
+        // Not merged by `==`, that would mix 0.0 and -0.0
+        let same = |c: &T| c.is_identical(n);
+        if let Some(constant) = self.program.constants.iter().position(same) {
+            constant as u32
+        } else {
+            self.program.constants.push(n);
+            (self.program.constants.len() - 1) as u32
+        }
+    }
+
+    /// Append an instruction and return its address.
+    fn emit(&mut self, instr: Instr, span: &Span) -> usize {
+        self.program.code.push(instr);
+        self.program.spans.push(span.clone());
+        self.program.code.len() - 1
+    }
+}
@@ -1,16 +1,116 @@
 use alloc::string::String;
+use core::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 /// Compiler error.
 pub struct CalfErr {
+    /// Kind of error.
+    pub code: ErrCode,
     /// Error message.
     pub message: String,
-    /// Position where the error was found.
-    pub pos: Pos,
+    /// Code where the error was found.
+    pub span: Span,
 }
 
-#[derive(Debug, Default, Clone)]
-/// Position of language element in the code.
+impl fmt::Display for CalfErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.span.start)
+    }
+}
+
+impl core::error::Error for CalfErr {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Kind of error. Each one has a stable code, grouped by the stage that reports it:
+/// `E01xx` lexer, `E02xx` parser, `E03xx` semantic checks and `E04xx` runtime.
+pub enum ErrCode {
+    /// Text that doesn't match any token.
+    UnrecognizedLexeme,
+    /// Number literal that can't be represented by the numeric type.
+    InvalidNumber,
+    /// Block comment without its closing `*/`.
+    UnclosedComment,
+    /// Token that can't appear at this point.
+    UnexpectedToken,
+    /// Missing comma, colon or closing delimiter.
+    MissingDelimiter,
+    /// Group opened and never closed before the end of the code.
+    Unclosed,
+    /// Reserved word used as a name.
+    ReservedWord,
+    /// Two statements in the same line without a `;` between them.
+    MissingSeparator,
+    /// Symbol that is not defined, or used before its definition.
+    UndefinedSymbol,
+    /// Function that uses a variable that is not one of its parameters.
+    CapturedVariable,
+    /// Function defined inside another function.
+    NestedLambda,
+    /// Function with two parameters with the same name.
+    DuplicatedParameter,
+    /// Function called with the wrong number of arguments.
+    ArityMismatch,
+    /// Something that is not a function is called, or a host function is used as a value.
+    NotAFunction,
+    /// Assignment to the name of a host function.
+    HostRedefinition,
+    /// Operation applied to a value of the wrong type.
+    TypeMismatch,
+    /// Integer division or remainder by zero, or zero to a negative integer power.
+    DivisionByZero,
+    /// Index outside of a vector.
+    OutOfBounds,
+    /// Element-wise operation between vectors of different length.
+    LengthMismatch,
+    /// Range with a size that is not a non negative integer.
+    InvalidRange,
+    /// Too many nested function calls.
+    CallDepth,
+    /// Global variable without a value when the program runs.
+    UndefinedVariable,
+    /// Program that can't run, because it has syntax errors or was compiled with a different registry.
+    InvalidProgram,
+}
+
+impl ErrCode {
+    /// Stable code of the error, like `E0201`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrCode::UnrecognizedLexeme => "E0101",
+            ErrCode::InvalidNumber => "E0102",
+            ErrCode::UnclosedComment => "E0103",
+            ErrCode::UnexpectedToken => "E0201",
+            ErrCode::MissingDelimiter => "E0202",
+            ErrCode::Unclosed => "E0203",
+            ErrCode::ReservedWord => "E0204",
+            ErrCode::MissingSeparator => "E0205",
+            ErrCode::UndefinedSymbol => "E0301",
+            ErrCode::CapturedVariable => "E0302",
+            ErrCode::NestedLambda => "E0303",
+            ErrCode::DuplicatedParameter => "E0304",
+            ErrCode::ArityMismatch => "E0305",
+            ErrCode::NotAFunction => "E0306",
+            ErrCode::HostRedefinition => "E0307",
+            ErrCode::TypeMismatch => "E0401",
+            ErrCode::DivisionByZero => "E0402",
+            ErrCode::OutOfBounds => "E0403",
+            ErrCode::LengthMismatch => "E0404",
+            ErrCode::InvalidRange => "E0405",
+            ErrCode::CallDepth => "E0406",
+            ErrCode::UndefinedVariable => "E0407",
+            ErrCode::InvalidProgram => "E0408",
+        }
+    }
+}
+
+impl fmt::Display for ErrCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+/// Position of language element in the code. Rows and columns start at 0.
 pub struct Pos {
     pub row: usize,
     pub col: usize,
@@ -21,3 +121,45 @@ impl Pos {
         Self { row, col }
     }
 }
+
+impl fmt::Display for Pos {
+    /// Displayed as `line:column`, starting at 1.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.row + 1, self.col + 1)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+/// Range of code occupied by a language element. The end position is not included.
+pub struct Span {
+    pub start: Pos,
+    pub end: Pos,
+    /// Offset in bytes of the start position from the beginning of the code.
+    pub byte_offset: usize,
+}
+
+impl Span {
+    pub fn new(start: Pos, end: Pos, byte_offset: usize) -> Self {
+        Self {
+            start,
+            end,
+            byte_offset,
+        }
+    }
+
+    /// Span from the start of `self` to the end of `other`.
+    pub fn to(&self, other: &Span) -> Self {
+        Self {
+            start: self.start.clone(),
+            end: other.end.clone(),
+            byte_offset: self.byte_offset,
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    /// Displayed as `line:column-line:column`, starting at 1.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
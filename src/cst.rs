@@ -0,0 +1,159 @@
+use crate::{
+    common::Span,
+    lexer::{RawToken, Scanner, TokenKind},
+};
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+/// Whitespace or comment.
+pub struct Trivia {
+    pub kind: TokenKind,
+    pub text: String,
+    pub span: Span,
+}
+
+impl Trivia {
+    fn raw(&self) -> RawToken<'_> {
+        RawToken {
+            kind: Some(self.kind),
+            text: &self.text,
+            span: self.span.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Token of the code, with the trivia that comes before it.
+pub struct CstToken {
+    pub leading: Vec<Trivia>,
+    /// Kind of token, or `None` if the text is not valid CALF code.
+    pub kind: Option<TokenKind>,
+    pub text: String,
+    pub span: Span,
+}
+
+impl CstToken {
+    fn raw(&self) -> RawToken<'_> {
+        RawToken {
+            kind: self.kind,
+            text: &self.text,
+            span: self.span.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// Concrete Syntax Tree. Lossless representation of the code, with all the tokens, including whitespace,
+/// comments and invalid text. Converting it into a string reproduces the code byte by byte.
+///
+/// The AST can be derived from it with [`Ast::from_cst`](crate::Ast::from_cst).
+pub struct Cst {
+    pub tokens: Vec<CstToken>,
+    /// Trivia after the last token.
+    pub trailing: Vec<Trivia>,
+}
+
+impl Cst {
+    /// Scan the code. It never fails, invalid text is kept in tokens without a kind.
+    pub fn parse(code: &str) -> Self {
+        let mut cst = Self::default();
+        let mut leading = Vec::new();
+        for raw in Scanner::new(code) {
+            match raw.kind {
+                Some(kind) if kind.is_trivia() => leading.push(Trivia {
+                    kind,
+                    text: raw.text.into(),
+                    span: raw.span,
+                }),
+                kind => cst.tokens.push(CstToken {
+                    leading: core::mem::take(&mut leading),
+                    kind,
+                    text: raw.text.into(),
+                    span: raw.span,
+                }),
+            }
+        }
+        cst.trailing = leading;
+        cst
+    }
+
+    /// Iterate over all the pieces of code, in order, as raw tokens.
+    pub(crate) fn raw_tokens(&self) -> impl Iterator<Item = RawToken<'_>> {
+        self.tokens
+            .iter()
+            .flat_map(|token| {
+                token
+                    .leading
+                    .iter()
+                    .map(Trivia::raw)
+                    .chain(Some(token.raw()))
+            })
+            .chain(self.trailing.iter().map(Trivia::raw))
+    }
+}
+
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for raw in self.raw_tokens() {
+            f.write_str(raw.text)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::Ast, common::Pos};
+    use alloc::string::ToString;
+
+    const CODES: &[&str] = &[
+        "",
+        "a = 1 + 2",
+        "\t x\t=  [1 ,2]  // trailing comment\n\n",
+        "/// Doc\nf2 = f(a) a * 2\r\ny = f2{3}\r\n",
+        "año = \"ñ\" + π € 😀\n",
+        "a = 1 $ @ ` 2\n?? :: ..",
+        "/* outer /* inner */ still outer */ x /* tail */",
+        "x = 1 /* unclosed /* nested */ comment\ny = 2",
+        "  \n\t// only trivia\n/* and more */  ",
+    ];
+
+    #[test]
+    fn round_trip() {
+        for code in CODES {
+            assert_eq!(Cst::parse(code).to_string(), *code);
+        }
+    }
+
+    #[test]
+    fn same_ast_as_parse() {
+        for code in CODES {
+            let (expected, expected_errors) = Ast::<f64>::parse(code);
+            let (ast, errors) = Ast::<f64>::from_cst(&Cst::parse(code));
+            assert_eq!(ast.statements, expected.statements, "{}", code);
+            assert!(ast.exprs().eq(expected.exprs()), "{}", code);
+            assert_eq!(errors, expected_errors, "{}", code);
+        }
+    }
+
+    #[test]
+    fn trivia_and_invalid_text() {
+        let cst = Cst::parse("x /* a /* b */ c */\r\n€ // end");
+        let kinds: Vec<_> = cst.tokens.iter().map(|token| token.kind).collect();
+        assert_eq!(
+            kinds,
+            [Some(TokenKind::Ident), None, Some(TokenKind::EOL), None]
+        );
+        // The nested comment is one piece of trivia
+        let leading: Vec<_> = cst.tokens[1].leading.iter().map(|t| t.kind).collect();
+        assert_eq!(leading, [TokenKind::Whitespace, TokenKind::BlockComment]);
+        assert_eq!(cst.tokens[1].text, "\r");
+        assert_eq!(cst.tokens[3].text, "€");
+        // Columns count characters, not bytes
+        assert_eq!(cst.trailing[0].span.start, Pos::new(1, 1));
+        assert_eq!(cst.trailing[1].kind, TokenKind::Comment);
+        assert_eq!(cst.trailing[1].text, "// end");
+    }
+}
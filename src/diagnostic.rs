@@ -0,0 +1,255 @@
+use crate::common::{CalfErr, ErrCode, Span};
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+/// Error ready to be shown to the user, with extra information to help fixing it.
+pub struct Diagnostic {
+    pub code: ErrCode,
+    pub message: String,
+    pub span: Span,
+    /// Text shown next to the underlined code.
+    pub label: Option<String>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(code: ErrCode, message: &str, span: Span) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            span,
+            label: None,
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+}
+
+impl From<CalfErr> for Diagnostic {
+    /// Convert an error, with the default label and help text of its kind.
+    fn from(err: CalfErr) -> Self {
+        let (label, help) = match err.code {
+            ErrCode::UnrecognizedLexeme => (Some("not valid in CALF code"), None),
+            ErrCode::InvalidNumber => (Some("invalid number"), None),
+            ErrCode::UnclosedComment => (
+                Some("comment opened here"),
+                Some("close it with '*/', block comments can be nested"),
+            ),
+            ErrCode::UnexpectedToken => (Some("unexpected here"), None),
+            ErrCode::MissingDelimiter => (Some("expected before this"), None),
+            ErrCode::Unclosed => (
+                Some("opened here"),
+                Some("add the missing closing delimiter"),
+            ),
+            ErrCode::ReservedWord => (
+                Some("reserved word"),
+                Some("'f' is used to define functions, choose another name"),
+            ),
+            ErrCode::MissingSeparator => (
+                Some("expected a new line or ';' before this"),
+                Some("put each statement in its own line, or separate them with ';'"),
+            ),
+            ErrCode::UndefinedSymbol => (
+                Some("not defined at this point"),
+                Some("assign it before the statement that uses it"),
+            ),
+            ErrCode::CapturedVariable => (
+                Some("not a parameter"),
+                Some("pass the value as an argument of the function"),
+            ),
+            ErrCode::NestedLambda => (
+                Some("function defined inside another function"),
+                Some("assign it to a name at the top level and call it by name"),
+            ),
+            ErrCode::DuplicatedParameter => (Some("parameter names must be unique"), None),
+            ErrCode::ArityMismatch => (Some("wrong number of arguments"), None),
+            ErrCode::NotAFunction => (Some("not a function"), None),
+            ErrCode::HostRedefinition => (
+                Some("defined by the host"),
+                Some("choose another name for the variable"),
+            ),
+            ErrCode::DivisionByZero => (Some("the divisor is zero"), None),
+            ErrCode::LengthMismatch => (
+                Some("vectors of different length"),
+                Some("operations between vectors are element-wise, both must have the same length"),
+            ),
+            _ => (None, None),
+        };
+        Self {
+            code: err.code,
+            message: err.message,
+            span: err.span,
+            label: label.map(Into::into),
+            notes: Vec::new(),
+            help: help.map(Into::into),
+        }
+    }
+}
+
+/// Render diagnostics in a human readable format, showing the lines of `source` where they were found:
+///
+/// ```text
+/// error[E0202]: Expecting a comma
+///  --> 2:7
+///   |
+/// 2 | bar{1 2, 3}
+///   |       ^ expected before this
+/// ```
+pub fn render(source: &str, diagnostics: &[Diagnostic]) -> String {
+    let mut out = String::new();
+    for (i, diagnostic) in diagnostics.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        render_one(source, diagnostic, &mut out);
+    }
+    out
+}
+
+fn render_one(source: &str, diagnostic: &Diagnostic, out: &mut String) {
+    let start = &diagnostic.span.start;
+    let end = &diagnostic.span.end;
+    let line = source.lines().nth(start.row).unwrap_or("");
+    let line_num = format!("{}", start.row + 1);
+    let gutter = " ".repeat(line_num.len());
+
+    // Writing into a String can't fail
+    let _ = writeln!(out, "error[{}]: {}", diagnostic.code, diagnostic.message);
+    let _ = writeln!(out, "{}--> {}", gutter, start);
+    let _ = writeln!(out, "{} |", gutter);
+    let _ = writeln!(out, "{} | {}", line_num, line);
+
+    // Underline from the start to the end of the span, or to the end of the line if it takes multiple lines
+    let line_len = line.chars().count();
+    let end_col = if end.row == start.row {
+        end.col
+    } else {
+        line_len
+    };
+    let width = end_col.saturating_sub(start.col).max(1);
+    // Keep tabs, so the underline is aligned with the code
+    let indent: String = line
+        .chars()
+        .chain(core::iter::repeat(' '))
+        .take(start.col)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let _ = write!(out, "{} | {}{}", gutter, indent, "^".repeat(width));
+    if let Some(label) = &diagnostic.label {
+        let _ = write!(out, " {}", label);
+    }
+    out.push('\n');
+
+    if !diagnostic.notes.is_empty() || diagnostic.help.is_some() {
+        let _ = writeln!(out, "{} |", gutter);
+    }
+    for note in diagnostic.notes.iter() {
+        let _ = writeln!(out, "{} = note: {}", gutter, note);
+    }
+    if let Some(help) = &diagnostic.help {
+        let _ = writeln!(out, "{} = help: {}", gutter, help);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::Ast, common::Pos};
+
+    fn render_errors(code: &str) -> String {
+        let (_, errors) = Ast::<f64>::parse(code);
+        let diagnostics: Vec<_> = errors.into_iter().map(Diagnostic::from).collect();
+        render(code, &diagnostics)
+    }
+
+    #[test]
+    fn caret_after_tabs() {
+        let expected = "error[E0101]: Unrecognized lexeme: '$'\n \
+                        --> 1:8\n  \
+                        |\n\
+                        1 | \ta =\t1 $ 2\n  \
+                        | \t   \t  ^ not valid in CALF code\n";
+        assert_eq!(render_errors("\ta =\t1 $ 2"), expected);
+    }
+
+    #[test]
+    fn caret_after_multibyte_characters() {
+        // Columns count characters, not bytes
+        let expected = "error[E0101]: Unrecognized lexeme: '$'\n \
+                        --> 1:15\n  \
+                        |\n\
+                        1 | a = 1 /* é */ $ 2\n  \
+                        |               ^ not valid in CALF code\n";
+        assert_eq!(render_errors("a = 1 /* é */ $ 2"), expected);
+    }
+
+    #[test]
+    fn span_of_multiple_lines() {
+        // Only the first line is shown, underlined up to its end
+        let span = Span::new(Pos::new(9, 4), Pos::new(10, 2), 0);
+        let source = "\n\n\n\n\n\n\n\n\na = (1 +\n2)";
+        let diagnostic =
+            Diagnostic::new(ErrCode::TypeMismatch, "Not a number", span).with_label("vector");
+        let expected = "error[E0401]: Not a number\n  \
+                        --> 10:5\n   \
+                        |\n\
+                        10 | a = (1 +\n   \
+                        |     ^^^^ vector\n";
+        assert_eq!(render(source, &[diagnostic]), expected);
+    }
+
+    #[test]
+    fn notes_and_help() {
+        let span = Span::new(Pos::new(0, 4), Pos::new(0, 5), 4);
+        let diagnostic = Diagnostic::new(ErrCode::DivisionByZero, "Division by zero", span)
+            .with_note("the divisor is an input")
+            .with_note("integers can't be divided by zero")
+            .with_help("check the divisor with a ternary operator");
+        let expected = "error[E0402]: Division by zero\n \
+                        --> 1:5\n  \
+                        |\n\
+                        1 | a / b\n  \
+                        |     ^\n  \
+                        |\n  \
+                        = note: the divisor is an input\n  \
+                        = note: integers can't be divided by zero\n  \
+                        = help: check the divisor with a ternary operator\n";
+        assert_eq!(render("a / b", &[diagnostic]), expected);
+    }
+
+    #[test]
+    fn default_label_and_help() {
+        let code = "a = 1\nb = /* one\ntwo";
+        let expected = "error[E0103]: Unclosed block comment\n \
+                        --> 2:5\n  \
+                        |\n\
+                        2 | b = /* one\n  \
+                        |     ^^ comment opened here\n  \
+                        |\n  \
+                        = help: close it with '*/', block comments can be nested\n\
+                        \n\
+                        error[E0201]: Couldn't parse a valid expression\n \
+                        --> 3:4\n  \
+                        |\n\
+                        3 | two\n  \
+                        |    ^ unexpected here\n";
+        assert_eq!(render_errors(code), expected);
+    }
+}
@@ -0,0 +1,266 @@
+use crate::{
+    ast::Ast,
+    bytecode::Program,
+    common::{CalfErr, ErrCode, Span},
+    host::Registry,
+    number::CalfNumber,
+    semantic::Interface,
+    value::Value,
+    vm::{Vm, VmValue},
+};
+use alloc::{string::String, vec::Vec};
+
+/// Program compiled once, that can run many times with different inputs.
+pub struct Engine<T> {
+    program: Program<T>,
+    host: Registry<T>,
+    interface: Interface,
+}
+
+impl<T> Engine<T>
+where
+    T: CalfNumber,
+{
+    /// Compile a program. Returns all the syntax errors found, or the first semantic error.
+    pub fn new(code: &str) -> Result<Self, Vec<CalfErr>> {
+        Self::with_registry(code, Registry::new())
+    }
+
+    /// Compile a program that can call the functions of a host registry.
+    pub fn with_registry(code: &str, host: Registry<T>) -> Result<Self, Vec<CalfErr>> {
+        let (ast, errors) = Ast::parse(code);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let interface = ast.interface_with(&host).map_err(|err| vec![err])?;
+        let program = Program::compile_with(&ast, &host).map_err(|err| vec![err])?;
+        Ok(Self {
+            program,
+            host,
+            interface,
+        })
+    }
+
+    /// Inputs the program expects and names it defines, to validate the bindings before running it.
+    pub fn interface(&self) -> &Interface {
+        &self.interface
+    }
+
+    /// Compiled bytecode.
+    pub fn program(&self) -> &Program<T> {
+        &self.program
+    }
+
+    /// Create a context to bind the inputs, run the program and read the outputs.
+    pub fn context(&self) -> Context<'_, T> {
+        Context {
+            vm: Vm::with_registry(&self.program, &self.host),
+            inputs: &self.interface.inputs,
+        }
+    }
+}
+
+/// Inputs and outputs of the runs of an [`Engine`]. Inputs are borrowed, and outputs are the global
+/// variables assigned by the program.
+pub struct Context<'a, T> {
+    vm: Vm<'a, T>,
+    inputs: &'a [String],
+}
+
+impl<'a, T> Context<'a, T>
+where
+    T: CalfNumber,
+{
+    /// Bind an input to a number. Returns `false` if it's not an input of the program.
+    pub fn bind_number(&mut self, name: &str, n: T) -> bool {
+        self.is_input(name) && self.vm.set(name, Value::Number(n))
+    }
+
+    /// Bind an input to a slice, without copying it. Returns `false` if it's not an input of the program.
+    pub fn bind_slice(&mut self, name: &str, slice: &'a [T]) -> bool {
+        self.is_input(name) && self.vm.set_slice(name, slice)
+    }
+
+    fn is_input(&self, name: &str) -> bool {
+        self.inputs.iter().any(|input| input == name)
+    }
+
+    /// Run the program. Returns the value of the last expression statement.
+    /// The outputs of the previous run are kept until they are assigned again.
+    pub fn run(&mut self) -> Result<Option<VmValue<T>>, CalfErr> {
+        self.vm.run()
+    }
+
+    /// Get an output number.
+    pub fn number(&self, name: &str) -> Result<T, CalfErr> {
+        match self.vm.get(name) {
+            Some(Value::Number(n)) => Ok(*n),
+            _ => {
+                self.slice(name)?;
+                Err(CalfErr {
+                    code: ErrCode::TypeMismatch,
+                    message: format!("Output '{}' is a vector, not a number", name),
+                    span: Span::default(),
+                })
+            }
+        }
+    }
+
+    /// Get an output as a vector. Numbers are returned as vectors of one element.
+    pub fn vector(&self, name: &str) -> Result<Vec<T>, CalfErr> {
+        self.slice(name).map(<[T]>::to_vec)
+    }
+
+    /// Copy an output into a buffer of the same length. Numbers are copied into all the elements.
+    pub fn read(&self, name: &str, buffer: &mut [T]) -> Result<(), CalfErr> {
+        if let Some(Value::Number(n)) = self.vm.get(name) {
+            buffer.fill(*n);
+            return Ok(());
+        }
+        let slice = self.slice(name)?;
+        if slice.len() != buffer.len() {
+            return Err(CalfErr {
+                code: ErrCode::LengthMismatch,
+                message: format!(
+                    "Output '{}' has {} elements, but the buffer has {}",
+                    name,
+                    slice.len(),
+                    buffer.len()
+                ),
+                span: Span::default(),
+            });
+        }
+        buffer.copy_from_slice(slice);
+        Ok(())
+    }
+
+    fn slice(&self, name: &str) -> Result<&[T], CalfErr> {
+        self.vm
+            .get_slice(name)
+            .ok_or_else(|| match self.vm.get(name) {
+                Some(Value::Function(_)) => CalfErr {
+                    code: ErrCode::TypeMismatch,
+                    message: format!("Output '{}' is a function", name),
+                    span: Span::default(),
+                },
+                _ => CalfErr {
+                    code: ErrCode::UndefinedVariable,
+                    message: format!("Undefined output '{}'", name),
+                    span: Span::default(),
+                },
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "y = x\nscaled = x * k\ntotal = k + 1\ndouble = f(a) a * 2";
+
+    #[test]
+    fn bind_only_inputs() {
+        let engine = Engine::<f64>::new(CODE).unwrap();
+        let data = [1.0, 2.0];
+        let mut context = engine.context();
+        assert!(context.bind_slice("x", &data));
+        assert!(context.bind_number("k", 3.0));
+        // Outputs, functions and unknown names are not inputs
+        assert!(!context.bind_number("y", 1.0));
+        assert!(!context.bind_slice("total", &data));
+        assert!(!context.bind_number("double", 1.0));
+        assert!(!context.bind_number("unknown", 1.0));
+    }
+
+    #[test]
+    fn outputs() {
+        let engine = Engine::<f64>::new(CODE).unwrap();
+        let data = [1.0, 2.0];
+        let mut context = engine.context();
+        context.bind_slice("x", &data);
+        context.bind_number("k", 3.0);
+        context.run().unwrap();
+
+        // The slice is passed through without copying it
+        assert_eq!(context.slice("y").unwrap().as_ptr(), data.as_ptr());
+        assert_eq!(context.vector("y").unwrap(), [1.0, 2.0]);
+        assert_eq!(context.vector("scaled").unwrap(), [3.0, 6.0]);
+        assert_eq!(context.number("total").unwrap(), 4.0);
+        // Numbers are vectors of one element
+        assert_eq!(context.vector("total").unwrap(), [4.0]);
+
+        let code = |result: Result<(), CalfErr>| result.unwrap_err().code;
+        assert_eq!(
+            code(context.number("scaled").map(drop)),
+            ErrCode::TypeMismatch
+        );
+        assert_eq!(
+            code(context.number("double").map(drop)),
+            ErrCode::TypeMismatch
+        );
+        assert_eq!(
+            code(context.vector("double").map(drop)),
+            ErrCode::TypeMismatch
+        );
+        assert_eq!(
+            code(context.number("unknown").map(drop)),
+            ErrCode::UndefinedVariable
+        );
+        assert_eq!(
+            code(context.vector("unknown").map(drop)),
+            ErrCode::UndefinedVariable
+        );
+    }
+
+    #[test]
+    fn read_into_buffers() {
+        let engine = Engine::<f64>::new(CODE).unwrap();
+        let data = [1.0, 2.0];
+        let mut context = engine.context();
+        context.bind_slice("x", &data);
+        context.bind_number("k", 3.0);
+        context.run().unwrap();
+
+        let mut buffer = [0.0; 2];
+        context.read("scaled", &mut buffer).unwrap();
+        assert_eq!(buffer, [3.0, 6.0]);
+        // Numbers fill the buffer
+        context.read("total", &mut buffer).unwrap();
+        assert_eq!(buffer, [4.0, 4.0]);
+
+        let mut long = [0.0; 3];
+        let err = context.read("scaled", &mut long).unwrap_err();
+        assert_eq!(err.code, ErrCode::LengthMismatch);
+        assert_eq!(long, [0.0; 3]);
+        let err = context.read("double", &mut buffer).unwrap_err();
+        assert_eq!(err.code, ErrCode::TypeMismatch);
+        let err = context.read("unknown", &mut buffer).unwrap_err();
+        assert_eq!(err.code, ErrCode::UndefinedVariable);
+    }
+
+    #[test]
+    fn runs_with_new_inputs() {
+        let engine = Engine::<f64>::new(CODE).unwrap();
+        for (data, k) in [(vec![1.0], 2.0), (vec![5.0, 6.0, 7.0], -1.0)] {
+            let mut context = engine.context();
+            context.bind_slice("x", &data);
+            context.bind_number("k", k);
+            context.run().unwrap();
+            let expected: Vec<f64> = data.iter().map(|n| n * k).collect();
+            assert_eq!(context.vector("scaled").unwrap(), expected);
+        }
+        // Missing input
+        let mut context = engine.context();
+        context.bind_number("k", 1.0);
+        assert_eq!(context.run().unwrap_err().code, ErrCode::UndefinedVariable);
+    }
+
+    #[test]
+    fn invalid_programs() {
+        assert_eq!(Engine::<f64>::new("a = 1 +\nb = (").err().unwrap().len(), 1);
+        let errors = Engine::<f64>::new("a = $\nb = )").err().unwrap();
+        assert_eq!(errors.len(), 2);
+        let errors = Engine::<f64>::new("g = f(a) b").err().unwrap();
+        assert_eq!(errors[0].code, ErrCode::CapturedVariable);
+    }
+}
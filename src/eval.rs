@@ -0,0 +1,338 @@
+use crate::{
+    ast::Ast,
+    common::{CalfErr, ErrCode, Span},
+    host::{HostFunction, Registry},
+    lexer::TokenKind,
+    number::CalfNumber,
+    parser::{ExprId, Stmt, Syntagma},
+    value::{
+        binary, gather, indexation, range, select, short_circuit, slice, unary, Operand, Value,
+        INDEX, MAX_CALL_DEPTH, OPERAND, SELECT,
+    },
+};
+use alloc::{string::String, vec::Vec};
+use core::fmt::{self, Debug};
+use hashbrown::HashMap;
+
+/// Function defined in the code.
+pub struct Lambda<'a, T> {
+    /// AST where the function is defined.
+    pub ast: &'a Ast<T>,
+    pub params: &'a [String],
+    pub body: ExprId,
+}
+
+impl<'a, T> Debug for Lambda<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lambda")
+            .field("params", &self.params)
+            .field("body", &self.body)
+            .finish()
+    }
+}
+
+impl<'a, T> Clone for Lambda<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for Lambda<'a, T> {}
+
+/// Value produced by the evaluator.
+pub type EvalValue<'a, T> = Value<T, Lambda<'a, T>>;
+
+/// Local variables of a function call.
+struct Frame<'a, 'f, T> {
+    ast: &'a Ast<T>,
+    vars: &'f [(&'a str, EvalValue<'a, T>)],
+    depth: usize,
+}
+
+/// Tree-walking evaluator.
+pub struct Evaluator<'a, T> {
+    globals: HashMap<String, EvalValue<'a, T>>,
+    host: Option<&'a Registry<T>>,
+}
+
+impl<'a, T> Default for Evaluator<'a, T> {
+    fn default() -> Self {
+        Self {
+            globals: Default::default(),
+            host: None,
+        }
+    }
+}
+
+impl<'a, T> Evaluator<'a, T>
+where
+    T: CalfNumber,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an evaluator that can call the functions of a host registry.
+    pub fn with_registry(host: &'a Registry<T>) -> Self {
+        Self {
+            globals: Default::default(),
+            host: Some(host),
+        }
+    }
+
+    /// Define a global variable, usually an input of the program.
+    pub fn set(&mut self, name: &str, value: EvalValue<'a, T>) {
+        self.globals.insert(name.into(), value);
+    }
+
+    /// Get a global variable.
+    pub fn get(&self, name: &str) -> Option<&EvalValue<'a, T>> {
+        self.globals.get(name)
+    }
+
+    /// Run all statements of a program. Returns the value of the last expression statement.
+    pub fn run(&mut self, ast: &'a Ast<T>) -> Result<Option<EvalValue<'a, T>>, CalfErr> {
+        let mut result = None;
+        for stmt in ast.statements.iter() {
+            match stmt {
+                Stmt::Assign { name, value, .. } => {
+                    let value = self.eval(*value, &Frame::global(ast))?;
+                    self.globals.insert(name.clone(), value);
+                }
+                Stmt::Expr { expr, .. } => {
+                    result = Some(self.eval(*expr, &Frame::global(ast))?);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn eval(&self, id: ExprId, frame: &Frame<'a, '_, T>) -> Result<EvalValue<'a, T>, CalfErr> {
+        let expr = &frame.ast[id];
+        match &expr.syn {
+            Syntagma::Number(n) => Ok(Value::Number(*n)),
+            Syntagma::Identifier(id) => self.lookup(id, frame, &expr.span).cloned(),
+            Syntagma::Vector { values } => {
+                let mut vector = Vec::with_capacity(values.len());
+                for value in values {
+                    vector.push(self.number(*value, frame)?);
+                }
+                Ok(Value::Vector(vector))
+            }
+            Syntagma::Range { init, len, step } => {
+                let init = self.number(*init, frame)?;
+                let step = match step {
+                    Some(step) => self.number(*step, frame)?,
+                    None => T::zero(),
+                };
+                Ok(Value::Vector(range(init, *len, step, &expr.span)?))
+            }
+            Syntagma::Group { expr } => self.eval(*expr, frame),
+            Syntagma::Slice { vector, start, end } => {
+                let vector = self.vector(*vector, frame)?;
+                let start = match start {
+                    Some(start) => Some(self.number(*start, frame)?),
+                    None => None,
+                };
+                let end = match end {
+                    Some(end) => Some(self.number(*end, frame)?),
+                    None => None,
+                };
+                Ok(Value::Vector(slice(&vector, start, end, &expr.span)?))
+            }
+            Syntagma::Gather { vector, indexes } => {
+                let vector = self.vector(*vector, frame)?;
+                let mut result = Vec::with_capacity(indexes.len());
+                for index in indexes {
+                    let span = &frame.ast[*index].span;
+                    let value = self.eval(*index, frame)?;
+                    gather(
+                        &vector,
+                        Operand::owned(value, INDEX, span)?,
+                        &mut result,
+                        span,
+                    )?;
+                }
+                Ok(Value::Vector(result))
+            }
+            Syntagma::UnaryOp { op, child } => {
+                let value = self.eval(*child, frame)?;
+                unary(*op, Operand::owned(value, OPERAND, &expr.span)?)
+            }
+            Syntagma::BinaryOp {
+                op: TokenKind::Sharp,
+                left_child,
+                right_child,
+            } => {
+                let vector = self.vector(*left_child, frame)?;
+                let index = self.eval(*right_child, frame)?;
+                let index = Operand::owned(index, INDEX, &expr.span)?;
+                indexation(&vector, index, &expr.span)
+            }
+            Syntagma::BinaryOp {
+                op,
+                left_child,
+                right_child,
+            } => {
+                let left = self.eval(*left_child, frame)?;
+                if let Value::Number(left) = left {
+                    if let Some(result) = short_circuit(*op, left) {
+                        return Ok(Value::Number(result));
+                    }
+                }
+                let left = Operand::owned(left, OPERAND, &expr.span)?;
+                let right = self.eval(*right_child, frame)?;
+                let right = Operand::owned(right, OPERAND, &expr.span)?;
+                binary(*op, left, right, &expr.span)
+            }
+            Syntagma::TernaryOp {
+                left_child,
+                mid_child,
+                right_child,
+            } => match self.eval(*left_child, frame)? {
+                Value::Number(cond) => {
+                    if cond.is_true() {
+                        self.eval(*mid_child, frame)
+                    } else {
+                        self.eval(*right_child, frame)
+                    }
+                }
+                // Element-wise selection: both branches are evaluated.
+                Value::Vector(mask) => {
+                    let then_value = self.eval(*mid_child, frame)?;
+                    let then_value = Operand::owned(then_value, SELECT, &expr.span)?;
+                    let else_value = self.eval(*right_child, frame)?;
+                    let else_value = Operand::owned(else_value, SELECT, &expr.span)?;
+                    select(&mask, then_value, else_value, &expr.span)
+                }
+                Value::Function(_) => Err(CalfErr {
+                    code: ErrCode::TypeMismatch,
+                    message: "A function can't be used as a condition".into(),
+                    span: frame.ast[*left_child].span.clone(),
+                }),
+            },
+            Syntagma::Call { func, args } => {
+                if let Some(host) = self.host_function(func, frame) {
+                    let mut values = Vec::with_capacity(args.len());
+                    for arg in args {
+                        values.push(self.eval(*arg, frame)?);
+                    }
+                    let message = host.function_arg();
+                    let operands = values
+                        .iter()
+                        .map(|value| Operand::borrowed(value, &message, &expr.span))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    return host.call(&operands, &expr.span);
+                }
+                let lambda = match self.lookup(func, frame, &expr.span)? {
+                    Value::Function(lambda) => *lambda,
+                    _ => {
+                        return Err(CalfErr {
+                            code: ErrCode::NotAFunction,
+                            message: format!("'{}' is not a function", func),
+                            span: expr.span.clone(),
+                        })
+                    }
+                };
+                if lambda.params.len() != args.len() {
+                    return Err(CalfErr {
+                        code: ErrCode::ArityMismatch,
+                        message: format!(
+                            "Function '{}' expects {} arguments, got {}",
+                            func,
+                            lambda.params.len(),
+                            args.len()
+                        ),
+                        span: expr.span.clone(),
+                    });
+                }
+                if frame.depth >= MAX_CALL_DEPTH {
+                    return Err(CalfErr {
+                        code: ErrCode::CallDepth,
+                        message: "Maximum call depth exceeded".into(),
+                        span: expr.span.clone(),
+                    });
+                }
+                let mut vars = Vec::with_capacity(args.len());
+                for (param, arg) in lambda.params.iter().zip(args.iter()) {
+                    vars.push((param.as_str(), self.eval(*arg, frame)?));
+                }
+                let call_frame = Frame {
+                    ast: lambda.ast,
+                    vars: &vars,
+                    depth: frame.depth + 1,
+                };
+                self.eval(lambda.body, &call_frame)
+            }
+            Syntagma::Lambda { params, body } => Ok(Value::Function(Lambda {
+                ast: frame.ast,
+                params,
+                body: *body,
+            })),
+            Syntagma::Error => Err(CalfErr {
+                code: ErrCode::InvalidProgram,
+                message: "Can't evaluate an expression with syntax errors".into(),
+                span: expr.span.clone(),
+            }),
+        }
+    }
+
+    /// Evaluate an expression that must produce a number.
+    fn number(&self, id: ExprId, frame: &Frame<'a, '_, T>) -> Result<T, CalfErr> {
+        match self.eval(id, frame)? {
+            Value::Number(n) => Ok(n),
+            _ => Err(CalfErr {
+                code: ErrCode::TypeMismatch,
+                message: "Expected a number".into(),
+                span: frame.ast[id].span.clone(),
+            }),
+        }
+    }
+
+    /// Evaluate an expression that must produce a vector.
+    fn vector(&self, id: ExprId, frame: &Frame<'a, '_, T>) -> Result<Vec<T>, CalfErr> {
+        match self.eval(id, frame)? {
+            Value::Vector(v) => Ok(v),
+            _ => Err(CalfErr {
+                code: ErrCode::TypeMismatch,
+                message: "Only vectors can be indexed".into(),
+                span: frame.ast[id].span.clone(),
+            }),
+        }
+    }
+
+    /// Find a host function, unless the name is shadowed by a variable.
+    fn host_function(&self, id: &str, frame: &Frame<'a, '_, T>) -> Option<&'a HostFunction<T>> {
+        if frame.vars.iter().any(|(name, _)| *name == id) || self.globals.contains_key(id) {
+            return None;
+        }
+        let host = self.host?;
+        host.find(id).and_then(|index| host.get(index))
+    }
+
+    fn lookup<'s>(
+        &'s self,
+        id: &str,
+        frame: &'s Frame<'a, '_, T>,
+        span: &Span,
+    ) -> Result<&'s EvalValue<'a, T>, CalfErr> {
+        if let Some((_, value)) = frame.vars.iter().find(|(name, _)| *name == id) {
+            return Ok(value);
+        }
+        self.globals.get(id).ok_or_else(|| CalfErr {
+            code: ErrCode::UndefinedVariable,
+            message: format!("Undefined symbol '{}'", id),
+            span: span.clone(),
+        })
+    }
+}
+
+impl<'a, 'f, T> Frame<'a, 'f, T> {
+    fn global(ast: &'a Ast<T>) -> Self {
+        Self {
+            ast,
+            vars: &[],
+            depth: 0,
+        }
+    }
+}
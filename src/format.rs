@@ -0,0 +1,694 @@
+use crate::{
+    ast::Ast,
+    common::{CalfErr, Pos},
+    cst::{Cst, CstToken, Trivia},
+    lexer::TokenKind,
+    operator::{self, Assoc, ATOM_PRECEDENCE},
+    parser::{ExprId, Stmt, Syntagma},
+};
+use alloc::{string::String, vec::Vec};
+
+/// Maximum width of a line. Longer expressions are broken in multiple lines.
+pub const MAX_WIDTH: usize = 100;
+/// Spaces of each indentation level.
+const INDENT: usize = 4;
+
+/// Format a program in the canonical style. Comments are kept, and number literals are written as they are.
+/// Returns the syntax errors if the code can't be parsed.
+///
+/// - One statement per line, with at most one empty line between them.
+/// - Binary operators surrounded by spaces, except `#`.
+/// - Expressions that don't fit in a line are broken after an operator, and the arguments of calls and the
+///   elements of lists are placed one per line.
+/// - Nested ternary operators are always broken, with the branches indented under the condition.
+/// - Parentheses that don't change the precedence are removed, unless there are comments inside.
+pub fn format(code: &str) -> Result<String, Vec<CalfErr>> {
+    let cst = Cst::parse(code);
+    // Number literals are copied from the CST, so the numeric type doesn't matter
+    let (ast, errors) = Ast::<f64>::from_cst(&cst);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let mut printer = Printer::new(&ast, &cst);
+    for stmt in ast.statements.iter() {
+        printer.separators();
+        printer.stmt(stmt);
+    }
+    printer.separators();
+    for trivia in cst.trailing.iter() {
+        // Comments can't break a layout that is not flat
+        let _ = printer.comment(trivia);
+    }
+    if !printer.line_start {
+        printer.newline();
+    }
+    Ok(printer.out)
+}
+
+/// The flat layout of an expression doesn't fit in the line, or it contains a line comment.
+struct Break;
+
+/// State of the printer, to restore it when a flat layout fails.
+struct Checkpoint {
+    len: usize,
+    next: usize,
+    flushed: usize,
+    col: usize,
+    line_start: bool,
+    last_row: usize,
+}
+
+/// Printer of the AST. The text of the tokens and the comments are taken from the CST, in order.
+struct Printer<'a> {
+    ast: &'a Ast<f64>,
+    tokens: &'a [CstToken],
+    /// Next token of the CST to print.
+    next: usize,
+    /// Number of tokens whose leading comments are already printed.
+    flushed: usize,
+    out: String,
+    indent: usize,
+    /// Column of the end of the output, in characters.
+    col: usize,
+    /// The output is at the start of a line, before the indentation.
+    line_start: bool,
+    /// Row in the code of the last printed token or comment.
+    last_row: usize,
+    /// Printing an expression in one line.
+    flat: bool,
+    /// Printing between statements, where empty lines are kept.
+    top: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn new(ast: &'a Ast<f64>, cst: &'a Cst) -> Self {
+        Self {
+            ast,
+            tokens: &cst.tokens,
+            next: 0,
+            flushed: 0,
+            out: String::new(),
+            indent: 0,
+            col: 0,
+            line_start: true,
+            last_row: 0,
+            flat: false,
+            top: true,
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        if let Some(token) = self.tokens.get(self.next) {
+            self.empty_line(token.span.start.row);
+        }
+        if !self.line_start {
+            self.newline();
+        }
+        self.top = false;
+        // Only the flat layouts can fail, and they are tried inside `expr`
+        let _ = match stmt {
+            Stmt::Assign { value, .. } => self
+                .token()
+                .and_then(|_| self.space())
+                .and_then(|_| self.token())
+                .and_then(|_| self.space())
+                .and_then(|_| self.expr(*value)),
+            Stmt::Expr { expr, .. } => self.expr(*expr),
+        };
+        self.top = true;
+    }
+
+    /// Print an expression in one line if it fits, or broken in lines otherwise.
+    fn expr(&mut self, id: ExprId) -> Result<(), Break> {
+        if self.flat {
+            return self.layout(id);
+        }
+        let checkpoint = self.checkpoint();
+        self.flat = true;
+        let result = self.layout(id);
+        self.flat = false;
+        if result.is_ok() {
+            return Ok(());
+        }
+        self.restore(checkpoint);
+        self.layout(id)
+    }
+
+    fn layout(&mut self, id: ExprId) -> Result<(), Break> {
+        let ast = self.ast;
+        // Unary plus, that is not in the AST, like in "+x" or "++x"
+        if self
+            .peek_token()
+            .is_some_and(|token| token.span.start == ast[id].span.start)
+        {
+            while self.peek() == Some(TokenKind::Plus) {
+                self.token()?;
+            }
+        }
+        match &ast[id].syn {
+            Syntagma::Number(_) => {
+                // Signs folded into the literal, like in "-5" or "+INF"
+                while matches!(self.peek(), Some(TokenKind::Minus) | Some(TokenKind::Plus)) {
+                    self.token()?;
+                }
+                self.token()
+            }
+            Syntagma::Identifier(_) | Syntagma::Error => self.token(),
+            Syntagma::Vector { values } => self.list(values),
+            Syntagma::Range { init, step, .. } => {
+                self.token()?; // "["
+                self.expr(*init)?;
+                self.token()?; // ";"
+                self.space()?;
+                self.token()?; // Size
+                if let Some(step) = step {
+                    self.token()?; // ";"
+                    self.space()?;
+                    self.expr(*step)?;
+                }
+                self.token() // "]"
+            }
+            Syntagma::Group { expr } if self.removable(id) => {
+                self.skip()?; // "("
+                self.expr(*expr)?;
+                self.skip() // ")"
+            }
+            Syntagma::Group { expr } => {
+                self.token()?; // "("
+                self.expr(*expr)?;
+                self.token() // ")"
+            }
+            Syntagma::Slice { vector, start, end } => {
+                self.expr(*vector)?;
+                self.token()?; // "#"
+                self.token()?; // "["
+                if let Some(start) = start {
+                    self.expr(*start)?;
+                }
+                self.token()?; // ".."
+                if let Some(end) = end {
+                    self.expr(*end)?;
+                }
+                self.token() // "]"
+            }
+            Syntagma::Gather { vector, indexes } => {
+                self.expr(*vector)?;
+                self.token()?; // "#"
+                self.token()?; // "["
+                for (i, index) in indexes.iter().enumerate() {
+                    if i > 0 {
+                        self.token()?; // ","
+                        self.space()?;
+                    }
+                    self.expr(*index)?;
+                }
+                self.token() // "]"
+            }
+            Syntagma::UnaryOp { child, .. } => {
+                self.token()?;
+                self.expr(*child)
+            }
+            Syntagma::BinaryOp {
+                op: TokenKind::Sharp,
+                left_child,
+                right_child,
+            } => {
+                self.expr(*left_child)?;
+                self.token()?;
+                self.expr(*right_child)
+            }
+            Syntagma::BinaryOp {
+                left_child,
+                right_child,
+                ..
+            } => {
+                self.expr(*left_child)?;
+                self.space()?;
+                self.token()?;
+                self.indented(|printer| {
+                    printer.line()?;
+                    printer.expr(*right_child)
+                })
+            }
+            Syntagma::TernaryOp {
+                left_child,
+                mid_child,
+                right_child,
+            } => {
+                // With another ternary in a branch, or in the branch of another ternary. Conditions can't be
+                // ternaries without a group.
+                let is_ternary = |id: ExprId| matches!(ast[id].syn, Syntagma::TernaryOp { .. });
+                let nested = is_ternary(self.ungroup(*mid_child))
+                    || is_ternary(self.ungroup(*right_child))
+                    || self.context(id).is_some_and(is_ternary);
+                if self.flat && nested {
+                    return Err(Break);
+                }
+                self.expr(*left_child)?;
+                self.space()?;
+                self.token()?; // "?"
+                self.indented(|printer| {
+                    printer.line()?;
+                    printer.expr(*mid_child)?;
+                    printer.line()?;
+                    printer.token()?; // ":"
+                    printer.space()?;
+                    printer.expr(*right_child)
+                })
+            }
+            Syntagma::Call { args, .. } => {
+                self.token()?; // Function name
+                self.list(args)
+            }
+            Syntagma::Lambda { params, body } => {
+                self.token()?; // "f"
+                self.token()?; // "("
+                for i in 0..params.len() {
+                    if i > 0 {
+                        self.token()?; // ","
+                        self.space()?;
+                    }
+                    self.token()?;
+                }
+                self.token()?; // ")"
+                self.indented(|printer| {
+                    printer.line()?;
+                    printer.expr(*body)
+                })
+            }
+        }
+    }
+
+    /// Print the elements of a list or the arguments of a call, between brackets and separated by commas.
+    /// When broken, each one goes in its own line.
+    fn list(&mut self, items: &[ExprId]) -> Result<(), Break> {
+        self.token()?; // Opening bracket
+        if !items.is_empty() {
+            self.indented(|printer| {
+                if !printer.flat {
+                    printer.line()?;
+                }
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        printer.token()?; // ","
+                        printer.line()?;
+                    }
+                    printer.expr(*item)?;
+                }
+                Ok(())
+            })?;
+            if !self.flat {
+                self.line()?;
+            }
+        }
+        self.token() // Closing bracket
+    }
+
+    fn indented(&mut self, f: impl FnOnce(&mut Self) -> Result<(), Break>) -> Result<(), Break> {
+        self.indent += 1;
+        let result = f(self);
+        self.indent -= 1;
+        result
+    }
+
+    /// Precedence of an expression, from the operator table.
+    fn precedence(&self, id: ExprId) -> u8 {
+        let infix = |op| operator::infix(op).map_or(0, |(precedence, _)| precedence);
+        let prefix = |op| operator::prefix(op).unwrap_or(0);
+        let precedence = match &self.ast[id].syn {
+            Syntagma::TernaryOp { .. } => infix(TokenKind::Question),
+            Syntagma::BinaryOp { op, .. } => infix(*op),
+            Syntagma::Slice { .. } | Syntagma::Gather { .. } => infix(TokenKind::Sharp),
+            Syntagma::UnaryOp { op, .. } => prefix(*op),
+            // The body takes everything after it
+            Syntagma::Lambda { .. } => 0,
+            _ => ATOM_PRECEDENCE,
+        };
+        // Signs that are not in the AST, like in "-5" or "+x"
+        match self.token_at(&self.ast[id].span.start) {
+            Some(CstToken {
+                kind: Some(op @ (TokenKind::Minus | TokenKind::Plus)),
+                ..
+            }) => precedence.min(prefix(*op)),
+            _ => precedence,
+        }
+    }
+
+    /// Minimum precedence of an expression to be written without parentheses, where it is.
+    fn required(&self, id: ExprId) -> u8 {
+        let ast = self.ast;
+        let parent = match ast.parent(id) {
+            Some(parent) => parent,
+            None => return 0,
+        };
+        match &ast[parent].syn {
+            Syntagma::Group { .. } if self.removable(parent) => self.required(parent),
+            Syntagma::BinaryOp {
+                op: TokenKind::Sharp,
+                left_child,
+                ..
+            } if *left_child != id => ATOM_PRECEDENCE,
+            Syntagma::BinaryOp { op, left_child, .. } => {
+                let (precedence, assoc) = operator::infix(*op).unwrap_or((0, Assoc::Left));
+                match (assoc, *left_child == id) {
+                    (Assoc::Left, true) | (Assoc::Right, false) => precedence,
+                    (Assoc::Left, false) | (Assoc::Right, true) => precedence + 1,
+                }
+            }
+            Syntagma::TernaryOp {
+                left_child,
+                right_child,
+                ..
+            } => {
+                let precedence = self.precedence(parent);
+                if *left_child == id {
+                    precedence + 1
+                } else if *right_child == id {
+                    precedence
+                } else {
+                    0
+                }
+            }
+            Syntagma::UnaryOp { .. } => self.precedence(parent),
+            Syntagma::Slice { vector, .. } | Syntagma::Gather { vector, .. } if *vector == id => {
+                self.precedence(parent)
+            }
+            // Between brackets, or the body of a lambda
+            _ => 0,
+        }
+    }
+
+    /// A group whose parentheses can be removed, because they don't change the precedence and there are no
+    /// comments inside.
+    fn removable(&self, id: ExprId) -> bool {
+        let span = &self.ast[id].span;
+        let tokens = &self.tokens[self.token_index(&span.start)..];
+        let comments = tokens
+            .iter()
+            .skip(1)
+            .take_while(|token| before(&token.span.start, &span.end))
+            .any(|token| {
+                token
+                    .leading
+                    .iter()
+                    .any(|trivia| trivia.kind != TokenKind::Whitespace)
+            });
+        let parent = self.context(id).map(|parent| &self.ast[parent].syn);
+        match &self.ast[id].syn {
+            // "-(5)" would be folded into a negative literal
+            Syntagma::Group { expr }
+                if matches!(parent, Some(Syntagma::UnaryOp { .. }))
+                    && matches!(self.ast[*expr].syn, Syntagma::Number(_)) =>
+            {
+                false
+            }
+            Syntagma::Group { expr } => !comments && self.precedence(*expr) >= self.required(id),
+            _ => false,
+        }
+    }
+
+    /// Expression inside the groups that will be removed.
+    fn ungroup(&self, mut id: ExprId) -> ExprId {
+        while let Syntagma::Group { expr } = self.ast[id].syn {
+            if !self.removable(id) {
+                break;
+            }
+            id = expr;
+        }
+        id
+    }
+
+    /// Parent of an expression, skipping the groups that will be removed.
+    fn context(&self, id: ExprId) -> Option<ExprId> {
+        let parent = self.ast.parent(id)?;
+        match self.ast[parent].syn {
+            Syntagma::Group { .. } if self.removable(parent) => self.context(parent),
+            _ => Some(parent),
+        }
+    }
+
+    /// Index of the token that starts at a position, or of the next one.
+    fn token_index(&self, pos: &Pos) -> usize {
+        self.tokens
+            .partition_point(|token| before(&token.span.start, pos))
+    }
+
+    fn token_at(&self, pos: &Pos) -> Option<&CstToken> {
+        self.tokens.get(self.token_index(pos))
+    }
+
+    /// Kind of the next token to print.
+    fn peek(&self) -> Option<TokenKind> {
+        self.peek_token()?.kind
+    }
+
+    /// Next token to print, skipping the newlines.
+    fn peek_token(&self) -> Option<&'a CstToken> {
+        self.tokens[self.next..]
+            .iter()
+            .find(|token| token.kind != Some(TokenKind::EOL))
+    }
+
+    /// Print the next token of the CST, with the comments before it.
+    fn token(&mut self) -> Result<(), Break> {
+        self.comments(&[TokenKind::EOL])?;
+        let tokens = self.tokens;
+        if let Some(token) = tokens.get(self.next) {
+            self.next += 1;
+            self.write(&token.text)?;
+            self.last_row = token.span.end.row;
+        }
+        Ok(())
+    }
+
+    /// Skip the next token of the CST without printing it, but with the comments before it.
+    fn skip(&mut self) -> Result<(), Break> {
+        self.comments(&[TokenKind::EOL])?;
+        self.next += 1;
+        Ok(())
+    }
+
+    /// Skip the newlines and `;` between statements, printing their comments.
+    fn separators(&mut self) {
+        // Layouts are never flat between statements
+        let _ = self.comments(&[TokenKind::EOL, TokenKind::Semicolon]);
+    }
+
+    /// Print the comments before the next token, skipping the tokens of the kinds in `skip`.
+    fn comments(&mut self, skip: &[TokenKind]) -> Result<(), Break> {
+        let tokens = self.tokens;
+        while let Some(token) = tokens.get(self.next) {
+            if self.flushed <= self.next {
+                for trivia in token.leading.iter() {
+                    self.comment(trivia)?;
+                }
+                self.flushed = self.next + 1;
+            }
+            match token.kind {
+                Some(kind) if skip.contains(&kind) => self.next += 1,
+                _ => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn comment(&mut self, trivia: &Trivia) -> Result<(), Break> {
+        if trivia.kind == TokenKind::Whitespace {
+            return Ok(());
+        }
+        let text = trivia.text.trim_end();
+        let line_comment = trivia.kind != TokenKind::BlockComment;
+        if self.flat && (line_comment || text.contains('\n')) {
+            return Err(Break);
+        }
+        let trailing = !self.line_start && trivia.span.start.row == self.last_row;
+        if trailing || (!line_comment && !self.line_start) {
+            if !self.out.ends_with([' ', '(', '[', '{']) {
+                self.space()?;
+            }
+        } else {
+            self.empty_line(trivia.span.start.row);
+            if !self.line_start {
+                self.newline();
+            }
+        }
+        self.write(text)?;
+        self.last_row = trivia.span.end.row;
+        if line_comment || !trailing {
+            self.newline();
+        } else {
+            self.space()?;
+        }
+        Ok(())
+    }
+
+    /// Keep one empty line between statements, if there was at least one in the code.
+    fn empty_line(&mut self, row: usize) {
+        if self.top && !self.out.is_empty() && row > self.last_row + 1 {
+            if !self.line_start {
+                self.newline();
+            }
+            if !self.out.ends_with("\n\n") {
+                self.out.push('\n');
+            }
+        }
+    }
+
+    /// Point where a broken layout starts a new line. In a flat layout it's a space.
+    fn line(&mut self) -> Result<(), Break> {
+        if self.flat {
+            return self.space();
+        }
+        // Comments at the end of the current line
+        self.comments(&[TokenKind::EOL])?;
+        if !self.line_start {
+            self.newline();
+        }
+        Ok(())
+    }
+
+    fn space(&mut self) -> Result<(), Break> {
+        self.write(" ")
+    }
+
+    fn write(&mut self, text: &str) -> Result<(), Break> {
+        if self.line_start {
+            self.line_start = false;
+            self.col = self.indent * INDENT;
+            self.out.push_str(&" ".repeat(self.col));
+        }
+        self.out.push_str(text);
+        self.col += text.chars().count();
+        if self.flat && self.col > MAX_WIDTH {
+            return Err(Break);
+        }
+        Ok(())
+    }
+
+    fn newline(&mut self) {
+        let len = self.out.trim_end_matches(' ').len();
+        self.out.truncate(len);
+        self.out.push('\n');
+        self.line_start = true;
+        self.col = 0;
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            len: self.out.len(),
+            next: self.next,
+            flushed: self.flushed,
+            col: self.col,
+            line_start: self.line_start,
+            last_row: self.last_row,
+        }
+    }
+
+    fn restore(&mut self, checkpoint: Checkpoint) {
+        self.out.truncate(checkpoint.len);
+        self.next = checkpoint.next;
+        self.flushed = checkpoint.flushed;
+        self.col = checkpoint.col;
+        self.line_start = checkpoint.line_start;
+        self.last_row = checkpoint.last_row;
+    }
+}
+
+fn before(a: &Pos, b: &Pos) -> bool {
+    (a.row, a.col) < (b.row, b.col)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Structure of the program, without spans and groups, that the formatter can remove.
+    fn shape(code: &str) -> String {
+        fn expr(ast: &Ast<f64>, id: ExprId) -> String {
+            let syn = &ast[id].syn;
+            if let Syntagma::Group { expr: inner } = syn {
+                return expr(ast, *inner);
+            }
+            let head = match syn {
+                Syntagma::Number(n) => format!("{:?}", n),
+                Syntagma::Identifier(id) => id.clone(),
+                Syntagma::Range { len, .. } => format!("range {}", len),
+                Syntagma::Slice { start, end, .. } => {
+                    format!("slice {} {}", start.is_some(), end.is_some())
+                }
+                Syntagma::UnaryOp { op, .. } | Syntagma::BinaryOp { op, .. } => format!("{:?}", op),
+                Syntagma::Call { func, .. } => format!("call {}", func),
+                Syntagma::Lambda { params, .. } => format!("f {:?}", params),
+                Syntagma::Vector { .. } => "vector".into(),
+                Syntagma::Gather { .. } => "gather".into(),
+                Syntagma::TernaryOp { .. } => "ternary".into(),
+                Syntagma::Group { .. } | Syntagma::Error => "error".into(),
+            };
+            let children: Vec<String> = syn.children().iter().map(|id| expr(ast, *id)).collect();
+            format!("{}({})", head, children.join(", "))
+        }
+        let (ast, errors) = Ast::<f64>::parse(code);
+        assert!(errors.is_empty(), "{:?} in {}", errors, code);
+        let stmts: Vec<String> = ast
+            .statements
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::Assign {
+                    name, value, doc, ..
+                } => format!("{:?} {} = {}", doc, name, expr(&ast, *value)),
+                Stmt::Expr { expr: id, .. } => expr(&ast, *id),
+            })
+            .collect();
+        stmts.join("\n")
+    }
+
+    const PROGRAMS: &[&str] = &[
+        "a=1+2*3",
+        "b = (1 + 2) * 3; c = ((b))\n\n\n\nd = -(b) + +c",
+        "x = [1,2 ,3]#[0 , 2]\ny = x#[..1]\nz = x#[ 1.. ]",
+        "r = [0;4;0.5] ^ 2 ^ (1 / 2) <? 3 >? 1",
+        "t = a > b ? a > c ? a : c : b > c ? b : c",
+        "/// Square of a number\nsq = f(n)   n * n // inline\n\n/* block */ sq{ 4 }",
+        "fact = f(n)\n    n <= 1 ? 1 : n * fact{n - 1}",
+        "v = some_function{argument_number_one, argument_number_two, argument_number_three} + another_function{argument_number_four}",
+        "w = [first_element_of_the_list, second_element_of_the_list, third_element_of_the_list, fourth_element]",
+        "m = (a || b) && !(c | d & 0xFF) == (e != 0b101)",
+    ];
+
+    #[test]
+    fn format_keeps_the_program() {
+        for code in PROGRAMS {
+            let formatted = format(code).unwrap();
+            assert_eq!(shape(code), shape(&formatted), "{}", formatted);
+        }
+    }
+
+    #[test]
+    fn format_is_idempotent() {
+        for code in PROGRAMS {
+            let formatted = format(code).unwrap();
+            assert_eq!(format(&formatted).unwrap(), formatted);
+        }
+    }
+
+    #[test]
+    fn format_keeps_the_comments() {
+        for code in PROGRAMS {
+            let formatted = format(code).unwrap();
+            for comment in ["// inline", "/* block */", "/// Square of a number"] {
+                assert_eq!(
+                    code.contains(comment),
+                    formatted.contains(comment),
+                    "{}",
+                    formatted
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn format_is_canonical() {
+        let formatted = format("a=1+2*3;b = (a)  #[ 0,1 ]\n\n\n\nc=-(5)").unwrap();
+        assert_eq!(formatted, "a = 1 + 2 * 3\nb = a#[0, 1]\n\nc = -(5)\n");
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{
+    common::{CalfErr, ErrCode, Span},
+    number::CalfNumber,
+    value::{check_len, Operand, Value},
+};
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+/// Function that receives one number per argument.
+pub type ScalarFn<T> = Box<dyn Fn(&[T]) -> T + Send + Sync>;
+
+/// Function that receives one slice per argument.
+pub type VectorFn<T> = Box<dyn Fn(&[&[T]]) -> Vec<T> + Send + Sync>;
+
+/// Rust function that can be called from the code.
+pub enum HostFn<T> {
+    /// Called once per element. Vector arguments are processed element-wise and numbers are broadcast.
+    Scalar(ScalarFn<T>),
+    /// Called once with all the arguments as slices. Numbers are passed as slices of one element.
+    Vector(VectorFn<T>),
+}
+
+/// Host function registered with a name.
+pub struct HostFunction<T> {
+    pub name: String,
+    pub arity: usize,
+    pub func: HostFn<T>,
+}
+
+/// Registry of host functions available to the code. Functions are `Send + Sync`, so a registry, and the
+/// engines that own it, can be shared between threads.
+pub struct Registry<T> {
+    functions: Vec<HostFunction<T>>,
+}
+
+impl<T> Default for Registry<T> {
+    fn default() -> Self {
+        Self {
+            functions: Default::default(),
+        }
+    }
+}
+
+impl<T> Registry<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a scalar function. If there is already a function with the same name, it's replaced.
+    pub fn register_scalar(
+        &mut self,
+        name: &str,
+        arity: usize,
+        func: impl Fn(&[T]) -> T + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.register(name, arity, HostFn::Scalar(Box::new(func)))
+    }
+
+    /// Register a vector function. If there is already a function with the same name, it's replaced.
+    pub fn register_vector(
+        &mut self,
+        name: &str,
+        arity: usize,
+        func: impl Fn(&[&[T]]) -> Vec<T> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.register(name, arity, HostFn::Vector(Box::new(func)))
+    }
+
+    fn register(&mut self, name: &str, arity: usize, func: HostFn<T>) -> &mut Self {
+        let function = HostFunction {
+            name: name.into(),
+            arity,
+            func,
+        };
+        if let Some(index) = self.find(name) {
+            self.functions[index] = function;
+        } else {
+            self.functions.push(function);
+        }
+        self
+    }
+
+    /// Find the index of a function.
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.functions.iter().position(|f| f.name == name)
+    }
+
+    /// Get a function by index.
+    pub fn get(&self, index: usize) -> Option<&HostFunction<T>> {
+        self.functions.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &HostFunction<T>> {
+        self.functions.iter()
+    }
+}
+
+impl<T: CalfNumber> HostFunction<T> {
+    /// Message of the error when an argument is a function.
+    pub(crate) fn function_arg(&self) -> String {
+        format!("Host function '{}' can't receive functions", self.name)
+    }
+
+    /// Call the function with already evaluated arguments.
+    pub(crate) fn call<F>(
+        &self,
+        args: &[Operand<'_, T>],
+        span: &Span,
+    ) -> Result<Value<T, F>, CalfErr> {
+        if args.len() != self.arity {
+            return Err(CalfErr {
+                code: ErrCode::ArityMismatch,
+                message: format!(
+                    "Function '{}' expects {} arguments, got {}",
+                    self.name,
+                    self.arity,
+                    args.len()
+                ),
+                span: span.clone(),
+            });
+        }
+        match &self.func {
+            HostFn::Scalar(func) => {
+                // Length of the vector arguments, if any
+                let mut len = None;
+                for arg in args {
+                    if let Operand::Vector(v) = arg {
+                        match len {
+                            Some(len) => check_len(len, v.len(), span)?,
+                            None => len = Some(v.len()),
+                        }
+                    }
+                }
+                let mut buffer = Vec::with_capacity(args.len());
+                let mut call = |i: usize| -> T {
+                    buffer.clear();
+                    buffer.extend(args.iter().map(|arg| match arg {
+                        Operand::Number(n) => *n,
+                        Operand::Vector(v) => v[i],
+                    }));
+                    func(&buffer)
+                };
+                match len {
+                    Some(len) => Ok(Value::Vector((0..len).map(&mut call).collect())),
+                    None => Ok(Value::Number(call(0))),
+                }
+            }
+            HostFn::Vector(func) => {
+                let slices: Vec<&[T]> = args
+                    .iter()
+                    .map(|arg| match arg {
+                        Operand::Number(n) => core::slice::from_ref(n),
+                        Operand::Vector(v) => v,
+                    })
+                    .collect();
+                Ok(Value::Vector(func(&slices)))
+            }
+        }
+    }
+}
@@ -1,17 +1,29 @@
-use crate::common::{CalfErr, Pos};
+use crate::{
+    common::{CalfErr, ErrCode, Pos, Span},
+    number::CalfNumber,
+};
 use alloc::string::String;
-use core::{fmt::Debug, str::FromStr};
+use core::marker::PhantomData;
 use logos::Logos;
 
-//TODO: Add tokens: NAN, +INF, -INF
-
-#[derive(Logos, Debug, PartialEq, Copy, Clone)]
-#[logos(skip r"[ \t]+")]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Logos, Debug, PartialEq, Eq, Copy, Clone)]
 /// Token types.
 pub enum TokenKind {
+    /// Spaces and tabs.
+    #[regex(r"[ \t]+")]
+    Whitespace,
+
     // Comment
     #[regex("//.*")]
     Comment,
+    /// Documentation of the next definition: `/// text`.
+    // Higher priority than line comments, that match the same text
+    #[regex("///.*", priority = 10)]
+    DocComment,
+    /// Block comment: `/* text */`, can be nested.
+    #[token("/*", block_comment)]
+    BlockComment,
 
     // End Of Line
     #[token("\n")]
@@ -48,6 +60,9 @@ pub enum TokenKind {
     Slash,
     #[token("%")]
     Percent,
+    /// Power: `a ^ b`.
+    #[token("^")]
+    Caret,
     #[token("<")]
     LesserThan,
     #[token(">")]
@@ -56,6 +71,12 @@ pub enum TokenKind {
     GtEqual,
     #[token("<=")]
     LtEqual,
+    /// Minimum: `a <? b`.
+    #[token("<?")]
+    LtQuestion,
+    /// Maximum: `a >? b`.
+    #[token(">?")]
+    GtQuestion,
     #[token("&")]
     And,
     #[token("&&")]
@@ -80,22 +101,39 @@ pub enum TokenKind {
     Sharp,
 
     // Literals
-    #[regex("-?[0-9]+")]
+    /// Decimal, hexadecimal (`0x`), octal (`0o`) or binary (`0b`) integer, digits can be separated with `_`.
+    #[regex("[0-9][0-9_]*")]
+    #[regex("0[xob][0-9a-zA-Z_]*")]
     Int,
-    //TODO: scientific notation: -9.09E-3, 9.09E+3
-    #[regex(r#"-?[0-9]+\.[0-9]+"#)]
+    #[regex(r#"[0-9][0-9_]*\.[0-9][0-9_]*([eE][+-]?[0-9]+)?"#)]
+    #[regex(r#"[0-9][0-9_]*[eE][+-]?[0-9]+"#)]
     Float,
+    /// Not a Number.
+    #[token("NAN")]
+    Nan,
+    /// Positive infinity, negative infinity is `-INF`.
+    #[token("INF")]
+    Inf,
 
     // Multichar
     #[regex(r#"[\p{Alphabetic}_]([\p{Alphabetic}_0-9]+)?"#)]
     Ident,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq)]
 pub enum Lexeme<T> {
     Number(T),
+    /// Text of an integer literal. It's converted by the parser, because a minus sign changes the range of
+    /// the type, like in `-128` for `i8`.
+    Int(String),
     Ident(String),
     Particle(TokenKind),
+    /// Text of a doc comment, without the `///`.
+    Doc(String),
+    /// Lexeme that couldn't be scanned.
+    Error,
+    /// End of the code.
     EOF,
     None,
 }
@@ -103,150 +141,309 @@ pub enum Lexeme<T> {
 #[derive(Debug)]
 pub struct Token<T> {
     pub lexeme: Lexeme<T>,
-    pub pos: Pos,
+    pub span: Span,
 }
 
 impl<T> Token<T> {
-    pub fn new(lexeme: Lexeme<T>, pos: Pos) -> Self {
-        Self { lexeme, pos }
+    pub fn new(lexeme: Lexeme<T>, span: Span) -> Self {
+        Self { lexeme, span }
     }
 }
 
-impl<T> FromToken<T> for Option<Token<T>> {
-    fn into_parts(self) -> Result<(Lexeme<T>, Pos), CalfErr> {
-        if let Some(token) = self {
-            Ok((token.lexeme, token.pos))
-        } else {
-            Err(CalfErr {
-                message: "Token is None".into(),
-                pos: Pos::default(),
-            })
-        }
+impl<T: CalfNumber> FromToken<T> for Token<T> {
+    fn into_parts(self) -> (Lexeme<T>, Span) {
+        (self.lexeme, self.span)
     }
 
-    fn into_particle(self) -> Result<(TokenKind, Pos), CalfErr> {
-        let (lexeme, pos) = self.into_parts()?;
-        if let Lexeme::Particle(t) = lexeme {
-            Ok((t, pos))
+    fn into_particle(self) -> Result<(TokenKind, Span), CalfErr> {
+        if let Lexeme::Particle(t) = self.lexeme {
+            Ok((t, self.span))
         } else {
             Err(CalfErr {
+                code: ErrCode::UnexpectedToken,
                 message: "Expected a particle".into(),
-                pos: Pos::default(),
+                span: self.span,
             })
         }
     }
 
-    fn into_ident(self) -> Result<(String, Pos), CalfErr> {
-        let (lexeme, pos) = self.into_parts()?;
-        if let Lexeme::Ident(s) = lexeme {
-            Ok((s, pos))
+    fn into_ident(self) -> Result<(String, Span), CalfErr> {
+        if let Lexeme::Ident(s) = self.lexeme {
+            Ok((s, self.span))
         } else {
             Err(CalfErr {
+                code: ErrCode::UnexpectedToken,
                 message: "Expected an identifier".into(),
-                pos: Pos::default(),
+                span: self.span,
             })
         }
     }
 
-    fn into_number(self) -> Result<(T, Pos), CalfErr> {
-        let (lexeme, pos) = self.into_parts()?;
-        if let Lexeme::Number(n) = lexeme {
-            Ok((n, pos))
-        } else {
-            Err(CalfErr {
+    fn into_number(self) -> Result<(T, Span), CalfErr> {
+        match self.lexeme {
+            Lexeme::Number(n) => Ok((n, self.span)),
+            Lexeme::Int(text) => match int_literal(&text, false) {
+                Ok(n) => Ok((n, self.span)),
+                Err(message) => Err(CalfErr {
+                    code: ErrCode::InvalidNumber,
+                    message,
+                    span: self.span,
+                }),
+            },
+            _ => Err(CalfErr {
+                code: ErrCode::TypeMismatch,
                 message: "Expected a number".into(),
-                pos: Pos::default(),
-            })
+                span: self.span,
+            }),
         }
     }
 }
 
 pub trait FromToken<T> {
-    fn into_parts(self) -> Result<(Lexeme<T>, Pos), CalfErr>;
-    fn into_particle(self) -> Result<(TokenKind, Pos), CalfErr>;
-    fn into_ident(self) -> Result<(String, Pos), CalfErr>;
-    fn into_number(self) -> Result<(T, Pos), CalfErr>;
+    fn into_parts(self) -> (Lexeme<T>, Span);
+    fn into_particle(self) -> Result<(TokenKind, Span), CalfErr>;
+    fn into_ident(self) -> Result<(String, Span), CalfErr>;
+    fn into_number(self) -> Result<(T, Span), CalfErr>;
+}
+
+impl TokenKind {
+    /// Whitespace and comments, that don't change the meaning of the code.
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            TokenKind::Whitespace
+                | TokenKind::Comment
+                | TokenKind::DocComment
+                | TokenKind::BlockComment
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Piece of code as it was scanned, before converting it into a [`Token`].
+pub struct RawToken<'a> {
+    /// Kind of token, or `None` if the text is not valid.
+    pub kind: Option<TokenKind>,
+    pub text: &'a str,
+    pub span: Span,
 }
 
-pub struct Lexer<'a> {
-    current_code: &'a str,
-    last_pos: Pos,
+/// Scanner of raw tokens. Every piece of the code, including whitespace and invalid text, produces a token.
+pub struct Scanner<'a> {
+    lexer: logos::Lexer<'a, TokenKind>,
+    /// Position at the end of the last scanned token. Columns count characters, not bytes.
+    pos: Pos,
 }
 
-impl<'a> Lexer<'a> {
+impl<'a> Scanner<'a> {
     pub fn new(code: &'a str) -> Self {
         Self {
-            current_code: code,
-            last_pos: Pos::new(0, 0),
+            lexer: TokenKind::lexer(code),
+            pos: Pos::new(0, 0),
         }
     }
+}
+
+impl<'a> Iterator for Scanner<'a> {
+    type Item = RawToken<'a>;
 
-    pub fn pos(&self) -> Pos {
-        self.last_pos.clone()
+    fn next(&mut self) -> Option<Self::Item> {
+        let kind = self.lexer.next()?.ok();
+        let text = self.lexer.slice();
+        let start = self.pos.clone();
+        for c in text.chars() {
+            if c == '\n' {
+                self.pos.row += 1;
+                self.pos.col = 0;
+            } else {
+                self.pos.col += 1;
+            }
+        }
+        let span = Span::new(start, self.pos.clone(), self.lexer.span().start);
+        Some(RawToken { kind, text, span })
     }
+}
 
-    pub fn scan_token<T>(&mut self) -> Result<Token<T>, CalfErr>
-    where
-        T: FromStr + Debug + PartialEq,
-        <T as FromStr>::Err: Debug,
-    {
-        if let Some((lexeme, lex_offs)) = TokenKind::lexer(self.current_code).spanned().next() {
-            let fragment = &self.current_code[lex_offs.start..lex_offs.end];
-            self.current_code = &self.current_code[lex_offs.end..];
-
-            let col = lex_offs.start + self.last_pos.col;
-            let mut next_pos = Pos::new(self.last_pos.row, col);
-
-            if let Ok(lexeme) = lexeme {
-                match lexeme {
-                    TokenKind::Comment => {
-                        let token = Token::new(Lexeme::None, next_pos.clone());
-                        next_pos.col += lex_offs.end - lex_offs.start;
-                        self.last_pos = next_pos;
-                        Ok(token)
-                    }
-                    TokenKind::EOL => {
-                        let token = Token::new(Lexeme::None, next_pos.clone());
-                        next_pos.row += 1;
-                        next_pos.col = 0;
-                        self.last_pos = next_pos;
-                        Ok(token)
-                    }
-                    TokenKind::Int | TokenKind::Float => match str::parse::<T>(fragment) {
-                        Ok(n) => {
-                            let token = Token::new(Lexeme::Number(n), next_pos.clone());
-                            next_pos.col += lex_offs.end - lex_offs.start;
-                            self.last_pos = next_pos;
-                            Ok(token)
-                        }
-                        Err(err) => Err(CalfErr {
-                            message: format!("{:?}", err),
-                            pos: next_pos,
-                        }),
-                    },
-                    TokenKind::Ident => {
-                        let token = Token::new(Lexeme::Ident(fragment.into()), next_pos.clone());
-                        next_pos.col += lex_offs.end - lex_offs.start;
-                        self.last_pos = next_pos;
-                        Ok(token)
-                    }
-                    _ => {
-                        let token = Token::new(Lexeme::Particle(lexeme), next_pos.clone());
-                        next_pos.col += lex_offs.end - lex_offs.start;
-                        self.last_pos = next_pos;
-                        Ok(token)
+/// Converter of raw tokens into tokens. Whitespace and comments produce tokens with a [`Lexeme::None`],
+/// except doc comments.
+pub struct Lexer<T, I> {
+    raw: I,
+    /// Empty span at the end of the last scanned token.
+    end: Span,
+    _number: PhantomData<T>,
+}
+
+impl<T, I> Lexer<T, I> {
+    pub fn from_raw(raw: I) -> Self {
+        Self {
+            raw,
+            end: Default::default(),
+            _number: PhantomData,
+        }
+    }
+
+    /// Empty span at the end of the last scanned token.
+    pub fn span(&self) -> Span {
+        self.end.clone()
+    }
+}
+
+impl<'a, T, I> Iterator for Lexer<T, I>
+where
+    T: CalfNumber,
+    I: Iterator<Item = RawToken<'a>>,
+{
+    type Item = Result<Token<T>, CalfErr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let RawToken {
+            kind: lexeme,
+            text: fragment,
+            span,
+        } = self.raw.next()?;
+        self.end = Span::new(
+            span.end.clone(),
+            span.end.clone(),
+            span.byte_offset + fragment.len(),
+        );
+
+        let token = match lexeme {
+            Some(TokenKind::Whitespace | TokenKind::Comment | TokenKind::BlockComment) => {
+                Token::new(Lexeme::None, span)
+            }
+            // Like in Rust, more than three slashes is a normal comment
+            Some(TokenKind::DocComment) if fragment.starts_with("////") => {
+                Token::new(Lexeme::None, span)
+            }
+            Some(TokenKind::DocComment) => {
+                let text = &fragment[3..];
+                let text = text.strip_prefix(' ').unwrap_or(text).trim_end();
+                Token::new(Lexeme::Doc(text.into()), span)
+            }
+            Some(TokenKind::Int) => match int_digits(fragment) {
+                Ok(_) => Token::new(Lexeme::Int(fragment.into()), span),
+                Err(message) => {
+                    return Some(Err(CalfErr {
+                        code: ErrCode::InvalidNumber,
+                        message,
+                        span,
+                    }))
+                }
+            },
+            Some(TokenKind::Float) => match T::from_float_literal(&fragment.replace('_', "")) {
+                Some(number) => Token::new(Lexeme::Number(number), span),
+                None => {
+                    return Some(Err(CalfErr {
+                        code: ErrCode::InvalidNumber,
+                        message: format!(
+                            "Float literal '{}' is not supported by {}",
+                            fragment,
+                            T::NAME
+                        ),
+                        span,
+                    }))
+                }
+            },
+            Some(kind @ (TokenKind::Nan | TokenKind::Inf)) => {
+                let number = if kind == TokenKind::Nan {
+                    T::nan()
+                } else {
+                    T::infinity()
+                };
+                match number {
+                    Some(number) => Token::new(Lexeme::Number(number), span),
+                    None => {
+                        return Some(Err(CalfErr {
+                            code: ErrCode::InvalidNumber,
+                            message: format!("'{}' is not supported by {}", fragment, T::NAME),
+                            span,
+                        }))
                     }
                 }
-            } else {
-                return Err(CalfErr {
+            }
+            Some(TokenKind::Ident) => Token::new(Lexeme::Ident(fragment.into()), span),
+            Some(kind) => Token::new(Lexeme::Particle(kind), span),
+            None if fragment.starts_with("/*") => {
+                // Point to the opening of the comment, not to the rest of the code
+                let end = Pos::new(span.start.row, span.start.col + 2);
+                return Some(Err(CalfErr {
+                    code: ErrCode::UnclosedComment,
+                    message: "Unclosed block comment".into(),
+                    span: Span::new(span.start, end, span.byte_offset),
+                }));
+            }
+            None => {
+                return Some(Err(CalfErr {
+                    code: ErrCode::UnrecognizedLexeme,
                     message: format!("Unrecognized lexeme: '{}'", fragment),
-                    pos: next_pos,
-                });
+                    span,
+                }))
+            }
+        };
+        Some(Ok(token))
+    }
+}
+
+/// Skip the rest of a block comment, including the nested ones. Fails if it's not closed.
+fn block_comment(lex: &mut logos::Lexer<TokenKind>) -> bool {
+    let rest = lex.remainder().as_bytes();
+    let mut depth = 1;
+    let mut i = 0;
+    while i < rest.len() {
+        if rest[i..].starts_with(b"/*") {
+            depth += 1;
+            i += 2;
+        } else if rest[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                lex.bump(i);
+                return true;
             }
         } else {
-            // EOF
-            let token = Token::new(Lexeme::EOF, self.last_pos.clone());
-            Ok(token)
+            i += 1;
         }
     }
+    lex.bump(rest.len());
+    false
+}
+
+/// Convert the text of an integer literal, negated if it's preceded by a minus sign.
+pub(crate) fn int_literal<T: CalfNumber>(fragment: &str, negative: bool) -> Result<T, String> {
+    let (radix, mut digits) = int_digits(fragment)?;
+    let zero = digits.chars().all(|c| c == '0');
+    if negative && !zero {
+        digits.insert(0, '-');
+    }
+    let n = T::from_int_literal(&digits, radix).ok_or_else(|| {
+        format!(
+            "Integer literal '{}{}' is out of range for {}",
+            if negative { "-" } else { "" },
+            fragment,
+            T::NAME
+        )
+    })?;
+    // Unsigned types can't parse "-0", the zero is negated instead, that keeps the sign of floats
+    Ok(if negative && zero { n.neg() } else { n })
+}
+
+/// Radix and digits of an integer literal, without the prefix and the separators.
+fn int_digits(fragment: &str) -> Result<(u32, String), String> {
+    let (radix, name, digits) = match fragment.get(..2) {
+        Some("0x") => (16, "hexadecimal", &fragment[2..]),
+        Some("0o") => (8, "octal", &fragment[2..]),
+        Some("0b") => (2, "binary", &fragment[2..]),
+        _ => (10, "decimal", fragment),
+    };
+    let digits: String = digits.chars().filter(|c| *c != '_').collect();
+    if digits.is_empty() {
+        return Err(format!("Integer literal '{}' has no digits", fragment));
+    }
+    if let Some(c) = digits.chars().find(|c| !c.is_digit(radix)) {
+        return Err(format!(
+            "Invalid digit '{}' in {} literal '{}'",
+            c, name, fragment
+        ));
+    }
+    Ok((radix, digits))
 }
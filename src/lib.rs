@@ -3,11 +3,41 @@
 #[macro_use]
 extern crate alloc;
 
+mod bytecode;
 mod common;
+mod cst;
+mod diagnostic;
+mod engine;
+mod eval;
+mod format;
+mod host;
 mod lexer;
+mod number;
+mod operator;
 mod parser;
 mod semantic;
+mod value;
+mod vm;
 
 // Reexport AST module.
 mod ast;
 pub use ast::*;
+pub use bytecode::{Function, Instr, Program};
+pub use common::{CalfErr, ErrCode, Pos, Span};
+pub use cst::{Cst, CstToken, Trivia};
+pub use diagnostic::{render, Diagnostic};
+pub use engine::{Context, Engine};
+pub use eval::{EvalValue, Evaluator, Lambda};
+pub use format::{format, MAX_WIDTH};
+pub use host::{HostFn, HostFunction, Registry, ScalarFn, VectorFn};
+pub use lexer::TokenKind;
+pub use number::CalfNumber;
+pub use parser::{Expr, ExprId, Stmt, Syntagma};
+pub use semantic::{Definition, Interface};
+pub use value::Value;
+pub use vm::{Vm, VmValue};
+
+// Compile the examples of the README
+#[cfg(doctest)]
+#[doc = include_str!("../README.md")]
+struct ReadmeDoctests;
@@ -1,9 +1,9 @@
-use calf;
+use std::io::Read;
 
 // Expression statements and assignment statements
 const _CODE_3: &str = r#"
-    10   5 + var
-    x = 10   y = (var + num) - 7
+    10; 5 + var
+    x = 10; y = (var + num) - 7
     20 + num - 8
     4.87
     num / 4 + 10 * (!!var - 2) % 3 == -num + 9 != 8 * num > !19
@@ -11,7 +11,7 @@ const _CODE_3: &str = r#"
 
 // Expression statements
 const _CODE_2: &str = r#"
-    10   5 + var
+    10; 5 + var
     num
     10 +// his is a comment ¿
         num
@@ -35,10 +35,12 @@ const _CODE_1: &str = r#"
         : 0
 
     foo = f(x,y) x * y + 2
+    sq = f(v) v * v
+    bar = f(a, g) g{a}
 
     foo{
         (x + 10) / y,
-        bar{x + y}
+        sq{x + y}
     }
 
     bar{
@@ -50,16 +52,100 @@ const _CODE_1: &str = r#"
     arr#i + 2
     arr#(x + y*10)
 
-    foo{10}#index
+    foo{10, arr}#index
+    arr#[2..8]
+    arr#[..4]
+    arr#[0,3,5]
+
+    [1, 2, x + y]
+    [0; 10; 0.5]
+    [x; 4]
 "#;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("fmt") {
+        std::process::exit(fmt(&args[1..]));
+    }
+
     println!("---- CALF ----\n");
 
     let code = _CODE_1;
-    let ast = calf::Ast::<f32>::build(code).expect("Error generating AST");
+    let ast = match calf::Ast::<f32>::build(code) {
+        Ok(ast) => ast,
+        Err(errors) => {
+            let diagnostics: Vec<calf::Diagnostic> = errors.into_iter().map(Into::into).collect();
+            eprint!("{}", calf::render(code, &diagnostics));
+            return;
+        }
+    };
     for stmt in ast.statements {
         println!("{:#?}\n", stmt);
         println!("------------------------------------\n");
     }
 }
+
+/// `calf fmt [--check] [FILE]...`
+///
+/// Format the files in place, or the standard input into the standard output if there are no files.
+/// With `--check`, the files are not modified, and it fails if any of them is not formatted.
+fn fmt(args: &[String]) -> i32 {
+    let check = args.iter().any(|arg| arg == "--check");
+    let files: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+
+    if files.is_empty() {
+        let mut code = String::new();
+        if let Err(err) = std::io::stdin().read_to_string(&mut code) {
+            eprintln!("error: can't read the standard input: {}", err);
+            return 1;
+        }
+        return match format_code("<stdin>", &code) {
+            Some(formatted) if check => (formatted != code) as i32,
+            Some(formatted) => {
+                print!("{}", formatted);
+                0
+            }
+            None => 1,
+        };
+    }
+
+    let mut status = 0;
+    for file in files {
+        let code = match std::fs::read_to_string(file) {
+            Ok(code) => code,
+            Err(err) => {
+                eprintln!("error: can't read {}: {}", file, err);
+                status = 1;
+                continue;
+            }
+        };
+        let Some(formatted) = format_code(file, &code) else {
+            status = 1;
+            continue;
+        };
+        if formatted == code {
+            continue;
+        }
+        if check {
+            eprintln!("{} is not formatted", file);
+            status = 1;
+        } else if let Err(err) = std::fs::write(file, formatted) {
+            eprintln!("error: can't write {}: {}", file, err);
+            status = 1;
+        }
+    }
+    status
+}
+
+/// Format the code of a file, or print its syntax errors.
+fn format_code(name: &str, code: &str) -> Option<String> {
+    match calf::format(code) {
+        Ok(formatted) => Some(formatted),
+        Err(errors) => {
+            let diagnostics: Vec<calf::Diagnostic> = errors.into_iter().map(Into::into).collect();
+            eprintln!("error: can't format {}", name);
+            eprint!("{}", calf::render(code, &diagnostics));
+            None
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use core::fmt::Debug;
+
+/// Numeric type a program can be compiled for.
+pub trait CalfNumber: Copy + PartialEq + PartialOrd + Debug {
+    /// Name of the type, used in error messages.
+    const NAME: &'static str;
+
+    fn zero() -> Self;
+    fn one() -> Self;
+
+    /// Parse the digits of an integer literal in the given radix, without prefix or separators, and with a
+    /// `-` sign if it's negative. Returns `None` if it's out of range.
+    fn from_int_literal(digits: &str, radix: u32) -> Option<Self>;
+    /// Parse the text of a float literal. Returns `None` if the type doesn't support it.
+    fn from_float_literal(text: &str) -> Option<Self>;
+
+    /// Not a Number, if the type supports it.
+    fn nan() -> Option<Self>;
+    /// Positive infinity, if the type supports it.
+    fn infinity() -> Option<Self>;
+
+    fn add(self, other: Self) -> Self;
+    fn sub(self, other: Self) -> Self;
+    fn mul(self, other: Self) -> Self;
+    /// Returns `None` when the division is not defined for the type (i.e. integer division by zero).
+    fn div(self, other: Self) -> Option<Self>;
+    /// Returns `None` when the remainder is not defined for the type (i.e. integer division by zero).
+    fn rem(self, other: Self) -> Option<Self>;
+    fn neg(self) -> Self;
+    /// Returns `None` when the power is not defined for the type (i.e. zero to a negative integer power).
+    fn pow(self, exp: Self) -> Option<Self>;
+    /// Bitwise and. Returns `None` if the type is not an integer.
+    fn bit_and(self, other: Self) -> Option<Self>;
+    /// Bitwise or. Returns `None` if the type is not an integer.
+    fn bit_or(self, other: Self) -> Option<Self>;
+
+    /// Convert into a vector index, if it is a non negative integer value.
+    fn to_index(self) -> Option<usize>;
+
+    fn from_bool(b: bool) -> Self {
+        if b {
+            Self::one()
+        } else {
+            Self::zero()
+        }
+    }
+
+    fn is_true(self) -> bool {
+        self != Self::zero()
+    }
+
+    /// Same value with the same representation. Unlike `==`, `0.0` and `-0.0` are different, and NaN is
+    /// identical to itself.
+    fn is_identical(self, other: Self) -> bool;
+
+    /// Not a Number, the only value that is not comparable to itself.
+    fn is_nan(self) -> bool {
+        self.partial_cmp(&self).is_none()
+    }
+}
+
+macro_rules! impl_calf_number_float {
+    ($($t:ident),*) => {
+        $(
+            impl CalfNumber for $t {
+                const NAME: &'static str = stringify!($t);
+
+                fn zero() -> Self { 0.0 }
+                fn one() -> Self { 1.0 }
+
+                fn from_int_literal(digits: &str, radix: u32) -> Option<Self> {
+                    let n: Self = if radix == 10 {
+                        digits.parse().ok()?
+                    } else {
+                        let (sign, digits) = match digits.strip_prefix('-') {
+                            Some(digits) => (-1.0, digits),
+                            None => (1.0, digits),
+                        };
+                        // Exact up to 128 bits, longer literals accumulate the digits
+                        let n = match u128::from_str_radix(digits, radix) {
+                            Ok(n) => n as Self,
+                            Err(_) => digits.chars().try_fold(0.0, |n: Self, c| {
+                                Some(n * radix as Self + c.to_digit(radix)? as Self)
+                            })?,
+                        };
+                        sign * n
+                    };
+                    // Out of range, in any radix, when it doesn't fit in a finite float
+                    if n.is_finite() {
+                        Some(n)
+                    } else {
+                        None
+                    }
+                }
+                fn from_float_literal(text: &str) -> Option<Self> { text.parse().ok() }
+
+                fn nan() -> Option<Self> { Some($t::NAN) }
+                fn infinity() -> Option<Self> { Some($t::INFINITY) }
+
+                fn add(self, other: Self) -> Self { self + other }
+                fn sub(self, other: Self) -> Self { self - other }
+                fn mul(self, other: Self) -> Self { self * other }
+                fn div(self, other: Self) -> Option<Self> { Some(self / other) }
+                fn rem(self, other: Self) -> Option<Self> { Some(self % other) }
+                fn neg(self) -> Self { -self }
+                fn pow(self, exp: Self) -> Option<Self> { Some(libm::Libm::<$t>::pow(self, exp)) }
+                fn bit_and(self, _: Self) -> Option<Self> { None }
+                fn bit_or(self, _: Self) -> Option<Self> { None }
+                fn is_identical(self, other: Self) -> bool { self.to_bits() == other.to_bits() }
+
+                fn to_index(self) -> Option<usize> {
+                    if self >= 0.0 && self <= usize::MAX as Self && (self as usize) as Self == self {
+                        Some(self as usize)
+                    } else {
+                        None
+                    }
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_calf_number_int {
+    ($($t:ident),*) => {
+        $(
+            impl CalfNumber for $t {
+                const NAME: &'static str = stringify!($t);
+
+                fn zero() -> Self { 0 }
+                fn one() -> Self { 1 }
+
+                fn from_int_literal(digits: &str, radix: u32) -> Option<Self> {
+                    Self::from_str_radix(digits, radix).ok()
+                }
+                fn from_float_literal(_: &str) -> Option<Self> { None }
+
+                fn nan() -> Option<Self> { None }
+                fn infinity() -> Option<Self> { None }
+
+                fn add(self, other: Self) -> Self { self.wrapping_add(other) }
+                fn sub(self, other: Self) -> Self { self.wrapping_sub(other) }
+                fn mul(self, other: Self) -> Self { self.wrapping_mul(other) }
+                // Like the other operations, the overflow of MIN / -1 wraps
+                fn div(self, other: Self) -> Option<Self> {
+                    if other == 0 { None } else { Some(self.wrapping_div(other)) }
+                }
+                fn rem(self, other: Self) -> Option<Self> {
+                    if other == 0 { None } else { Some(self.wrapping_rem(other)) }
+                }
+                fn neg(self) -> Self { self.wrapping_neg() }
+                fn pow(self, exp: Self) -> Option<Self> {
+                    if exp < Self::zero() {
+                        // Truncated like the integer division: 1 / self ^ -exp
+                        let inverse = Self::one().checked_div(self)?;
+                        return Some(if exp % 2 == 0 { inverse.wrapping_mul(inverse) } else { inverse });
+                    }
+                    let (mut base, mut exp, mut result) = (self, exp, Self::one());
+                    while exp > 0 {
+                        if exp % 2 == 1 {
+                            result = result.wrapping_mul(base);
+                        }
+                        base = base.wrapping_mul(base);
+                        exp /= 2;
+                    }
+                    Some(result)
+                }
+                fn bit_and(self, other: Self) -> Option<Self> { Some(self & other) }
+                fn bit_or(self, other: Self) -> Option<Self> { Some(self | other) }
+                fn is_identical(self, other: Self) -> bool { self == other }
+
+                fn to_index(self) -> Option<usize> { usize::try_from(self).ok() }
+            }
+        )*
+    };
+}
+
+impl_calf_number_float!(f32, f64);
+impl_calf_number_int!(i32, i64, u8);
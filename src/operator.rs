@@ -0,0 +1,89 @@
+use crate::lexer::TokenKind;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    /// `a - b - c` is `(a - b) - c`.
+    Left,
+    /// `a ^ b ^ c` is `a ^ (b ^ c)`.
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fixity {
+    /// Binary operator between its operands.
+    Infix(Assoc),
+    /// Unary operator before its operand.
+    Prefix,
+}
+
+/// Operators, from lower to higher precedence. The operators of a row have the same precedence.
+pub const OPERATORS: &[(Fixity, &[TokenKind])] = &[
+    // Ternary operator: "a ? b : c"
+    (Fixity::Infix(Assoc::Right), &[TokenKind::Question]),
+    // Logical operators bind looser than comparisons: "x >= 0 && y >= 0"
+    (Fixity::Infix(Assoc::Left), &[TokenKind::TwoOrs]),
+    (Fixity::Infix(Assoc::Left), &[TokenKind::TwoAnds]),
+    (
+        Fixity::Infix(Assoc::Left),
+        &[TokenKind::TwoEquals, TokenKind::NotEqual],
+    ),
+    (
+        Fixity::Infix(Assoc::Left),
+        &[
+            TokenKind::GreaterThan,
+            TokenKind::LesserThan,
+            TokenKind::GtEqual,
+            TokenKind::LtEqual,
+        ],
+    ),
+    // Minimum and maximum: "x >? lo <? hi" clamps x between lo and hi
+    (
+        Fixity::Infix(Assoc::Left),
+        &[TokenKind::LtQuestion, TokenKind::GtQuestion],
+    ),
+    // Bitwise operators bind tighter than comparisons: "x & 1 == 0"
+    (Fixity::Infix(Assoc::Left), &[TokenKind::Or]),
+    (Fixity::Infix(Assoc::Left), &[TokenKind::And]),
+    (
+        Fixity::Infix(Assoc::Left),
+        &[TokenKind::Plus, TokenKind::Minus],
+    ),
+    (
+        Fixity::Infix(Assoc::Left),
+        &[TokenKind::Star, TokenKind::Slash, TokenKind::Percent],
+    ),
+    (
+        Fixity::Prefix,
+        &[TokenKind::Not, TokenKind::Minus, TokenKind::Plus],
+    ),
+    // Power binds tighter than signs: "-2 ^ 2" is "-(2 ^ 2)"
+    (Fixity::Infix(Assoc::Right), &[TokenKind::Caret]),
+    // Indexation: "a#b"
+    (Fixity::Infix(Assoc::Left), &[TokenKind::Sharp]),
+];
+
+/// Precedence of the expressions that are not operations, like literals, calls or groups.
+pub const ATOM_PRECEDENCE: u8 = OPERATORS.len() as u8 + 1;
+
+/// Precedence and associativity of a binary operator. Higher precedences bind tighter, starting at 1.
+pub fn infix(kind: TokenKind) -> Option<(u8, Assoc)> {
+    find(kind, |fixity| match fixity {
+        Fixity::Infix(assoc) => Some(assoc),
+        Fixity::Prefix => None,
+    })
+}
+
+/// Precedence of a prefix operator.
+pub fn prefix(kind: TokenKind) -> Option<u8> {
+    find(kind, |fixity| (fixity == Fixity::Prefix).then_some(())).map(|(precedence, _)| precedence)
+}
+
+fn find<T>(kind: TokenKind, fixity: impl Fn(Fixity) -> Option<T>) -> Option<(u8, T)> {
+    OPERATORS
+        .iter()
+        .enumerate()
+        .find_map(|(i, (f, kinds))| match fixity(*f) {
+            Some(t) if kinds.contains(&kind) => Some((i as u8 + 1, t)),
+            _ => None,
+        })
+}
@@ -1,550 +1,1141 @@
 use crate::{
-    common::{CalfErr, Pos},
-    lexer::{FromToken, Lexeme, Lexer, Token, TokenKind},
+    common::{CalfErr, ErrCode, Span},
+    lexer::{int_literal, FromToken, Lexeme, Lexer, RawToken, Scanner, Token, TokenKind},
+    number::CalfNumber,
+    operator::{self, Assoc},
 };
-use alloc::{boxed::Box, collections::VecDeque, string::String, vec::Vec};
-use core::{fmt::Debug, str::FromStr};
+use alloc::{collections::VecDeque, string::String, vec::Vec};
 
-//TODO: create a Vec<Expr<T>>, and use indexes to this vec instead of Box<Expr<T>> to reduce allocations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Handle of an expression stored in the AST.
+pub struct ExprId(u32);
 
-#[derive(Debug)]
+impl ExprId {
+    pub(crate) fn new(index: usize) -> Self {
+        Self(index as u32)
+    }
+
+    /// Position of the expression in the AST arena.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 /// Syntactic unit.
 pub enum Syntagma<T> {
+    /// Number literal.
     Number(T),
+    /// Variable or function name.
     Identifier(String),
-    Vector {
-        values: Vec<Expr<T>>,
-        len: u64,
-    },
+    /// List literal in array form: `[a, b, c]`.
+    Vector { values: Vec<ExprId> },
+    /// List literal in range form: `[init; len; step]` or `[init; len]`.
     Range {
-        init: Box<Expr<T>>,
+        init: ExprId,
         len: T,
-        step: Box<Expr<T>>,
+        step: Option<ExprId>,
     },
-    Group {
-        expr: Box<Expr<T>>,
+    /// Expression between parenthesis.
+    Group { expr: ExprId },
+    /// Slice indexation: `vector#[start..end]`, both ends are optional.
+    Slice {
+        vector: ExprId,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
     },
-    UnaryOp {
-        op: TokenKind,
-        child: Box<Expr<T>>,
+    /// Set indexation: `vector#[i, j, k]`.
+    Gather {
+        vector: ExprId,
+        indexes: Vec<ExprId>,
     },
+    /// Unary operation: `-a`, `!a`.
+    UnaryOp { op: TokenKind, child: ExprId },
+    /// Binary operation: `a + b`, `a # b`, etc.
     BinaryOp {
         op: TokenKind,
-        left_child: Box<Expr<T>>,
-        right_child: Box<Expr<T>>,
+        left_child: ExprId,
+        right_child: ExprId,
     },
+    /// Ternary operation: `left ? mid : right`.
     TernaryOp {
-        left_child: Box<Expr<T>>,
-        mid_child: Box<Expr<T>>,
-        right_child: Box<Expr<T>>,
-    },
-    Call {
-        func: String,
-        args: Vec<Expr<T>>,
-    },
-    Lambda {
-        params: Vec<String>,
-        body: Box<Expr<T>>,
+        left_child: ExprId,
+        mid_child: ExprId,
+        right_child: ExprId,
     },
+    /// Function call: `func{a, b}`.
+    Call { func: String, args: Vec<ExprId> },
+    /// Function definition: `f(a, b) body`.
+    Lambda { params: Vec<String>, body: ExprId },
+    /// Expression that couldn't be parsed. The syntax error is reported separately.
+    Error,
+}
+
+impl<T> Syntagma<T> {
+    /// Child expressions, in source order.
+    pub fn children(&self) -> Vec<ExprId> {
+        match self {
+            Syntagma::Number(_) | Syntagma::Identifier(_) | Syntagma::Error => vec![],
+            Syntagma::Vector { values } => values.clone(),
+            Syntagma::Range { init, step, .. } => {
+                let mut children = vec![*init];
+                children.extend(step);
+                children
+            }
+            Syntagma::Group { expr } => vec![*expr],
+            Syntagma::Slice { vector, start, end } => {
+                let mut children = vec![*vector];
+                children.extend(start);
+                children.extend(end);
+                children
+            }
+            Syntagma::Gather { vector, indexes } => {
+                let mut children = vec![*vector];
+                children.extend(indexes);
+                children
+            }
+            Syntagma::UnaryOp { child, .. } => vec![*child],
+            Syntagma::BinaryOp {
+                left_child,
+                right_child,
+                ..
+            } => vec![*left_child, *right_child],
+            Syntagma::TernaryOp {
+                left_child,
+                mid_child,
+                right_child,
+            } => vec![*left_child, *mid_child, *right_child],
+            Syntagma::Call { args, .. } => args.clone(),
+            Syntagma::Lambda { body, .. } => vec![*body],
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 /// Expression.
 pub struct Expr<T> {
+    /// Syntactic unit of the expression.
     pub syn: Syntagma<T>,
-    pub pos: Pos,
+    /// Code of the expression.
+    pub span: Span,
 }
 
 impl<T> Expr<T> {
-    pub fn new(unit: Syntagma<T>, pos: Pos) -> Self {
-        Self { syn: unit, pos }
+    pub fn new(unit: Syntagma<T>, span: Span) -> Self {
+        Self { syn: unit, span }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 /// Statement.
-pub enum Stmt<T> {
-    Assign { name: String, value: Expr<T> },
-    Expr(Expr<T>),
+pub enum Stmt {
+    /// Assignment statement: `name = value`.
+    Assign {
+        name: String,
+        value: ExprId,
+        span: Span,
+        /// Text of the doc comments before the statement, one line each.
+        doc: Option<String>,
+    },
+    /// Expression statement.
+    Expr { expr: ExprId, span: Span },
 }
 
-pub struct Parser<'a, T> {
+pub struct Parser<T, I> {
     tokens: VecDeque<Token<T>>,
-    lexer: Lexer<'a>,
+    lexer: Lexer<T, I>,
+    /// Arena where all the parsed expressions are stored.
+    exprs: Vec<Expr<T>>,
+    /// Syntax errors found so far.
+    errors: Vec<CalfErr>,
+    /// Number of tokens consumed so far.
+    consumed: usize,
+    /// Span of the last consumed token.
+    last_span: Span,
+    /// Number of open brackets. Newlines are ignored inside them.
+    nesting: usize,
+    /// Doc comments scanned and not attached to a statement yet, with their byte offset.
+    docs: VecDeque<(usize, String)>,
 }
 
-impl<'a, T> Parser<'a, T>
+impl<'a, T> Parser<T, Scanner<'a>>
 where
-    T: FromStr + Debug + PartialEq,
-    <T as FromStr>::Err: Debug,
+    T: CalfNumber,
 {
     pub fn new(code: &'a str) -> Self {
+        Self::from_raw(Scanner::new(code))
+    }
+}
+
+impl<'a, T, I> Parser<T, I>
+where
+    T: CalfNumber,
+    I: Iterator<Item = RawToken<'a>>,
+{
+    /// Create a parser that takes the raw tokens from an iterator, instead of scanning the code.
+    pub fn from_raw(raw: I) -> Self {
         Self {
             tokens: Default::default(),
-            lexer: Lexer::new(code),
+            lexer: Lexer::from_raw(raw),
+            exprs: Default::default(),
+            errors: Default::default(),
+            consumed: 0,
+            last_span: Default::default(),
+            nesting: 0,
+            docs: Default::default(),
         }
     }
 
-    pub fn scan_stmt(&mut self) -> Result<Stmt<T>, CalfErr> {
-        let stmt = self.statement()?;
-        Ok(stmt)
+    /// Scan a statement, or return `None` if the code has ended. When it contains syntax errors, they are
+    /// collected and the parser skips to the next statement.
+    ///
+    /// Statements end at a new line or a `;`. New lines are ignored inside brackets and after an operator.
+    pub fn scan_stmt(&mut self) -> Option<Stmt> {
+        self.nesting = 0;
+        // Skip empty statements
+        while self.is_token(TokenKind::EOL, 0) || self.is_token(TokenKind::Semicolon, 0) {
+            self.token();
+        }
+        if self.ended() {
+            return None;
+        }
+        let consumed = self.consumed;
+        let start = self.next_span();
+        let doc = self.take_doc(start.byte_offset);
+        let stmt = match self.statement(doc) {
+            Ok(stmt) => {
+                self.end_of_statement();
+                stmt
+            }
+            Err(err) => {
+                // Make sure the parser advances
+                if self.consumed == consumed {
+                    self.token();
+                }
+                let expr = self.error_expr(err);
+                self.synchronize_stmt();
+                Stmt::Expr {
+                    expr,
+                    span: start.to(&self.last_span),
+                }
+            }
+        };
+        // Doc comments inside the statement don't document anything
+        self.take_doc(self.last_span.byte_offset);
+        Some(stmt)
     }
 
-    fn statement(&mut self) -> Result<Stmt<T>, CalfErr> {
-        if self.is_token(TokenKind::Ident, 0)? && self.is_token(TokenKind::Assign, 1)? {
-            self.assign_statement()
+    /// Take the doc comments found before a byte offset, joined in one text.
+    fn take_doc(&mut self, before: usize) -> Option<String> {
+        let mut doc: Option<String> = None;
+        while let Some((offset, _)) = self.docs.front() {
+            if *offset >= before {
+                break;
+            }
+            let (_, line) = self.docs.pop_front()?;
+            match &mut doc {
+                Some(doc) => {
+                    doc.push('\n');
+                    doc.push_str(&line);
+                }
+                None => doc = Some(line),
+            }
+        }
+        doc
+    }
+
+    /// Consume the separator after a statement.
+    fn end_of_statement(&mut self) {
+        if self.is_token(TokenKind::EOL, 0) || self.is_token(TokenKind::Semicolon, 0) {
+            self.token();
+        } else if self.is_error(0) {
+            // The lexeme that couldn't be scanned is already reported
+            self.synchronize_stmt();
+        } else if !self.ended() {
+            let span = self.next_span();
+            self.errors.push(CalfErr {
+                code: ErrCode::MissingSeparator,
+                message: "Expected a new line or ';' after the statement".into(),
+                span,
+            });
+            self.synchronize_stmt();
+        }
+    }
+
+    fn statement(&mut self, doc: Option<String>) -> Result<Stmt, CalfErr> {
+        if self.is_token(TokenKind::Ident, 0) && self.is_token(TokenKind::Assign, 1) {
+            self.assign_statement(doc)
         } else {
             // Otherwise, expression statement
             self.expression_statement()
         }
     }
 
-    fn assign_statement(&mut self) -> Result<Stmt<T>, CalfErr> {
-        let (name, pos) = self.token().into_ident()?;
+    fn assign_statement(&mut self, doc: Option<String>) -> Result<Stmt, CalfErr> {
+        let (name, name_span) = self.token().into_ident()?;
         if name == "f" {
             return Err(CalfErr {
+                code: ErrCode::ReservedWord,
                 message: "'f' is a reserved word".into(),
-                pos,
+                span: name_span,
             });
         }
         self.token().into_particle()?; // Consume "="
-        let value = self.expression()?;
-        Ok(Stmt::Assign { name, value })
+        self.skip_newlines();
+        let value = match self.expression() {
+            Ok(value) => value,
+            Err(err) => {
+                let value = self.error_expr(err);
+                self.synchronize_stmt();
+                value
+            }
+        };
+        let span = name_span.to(&self.last_span);
+        Ok(Stmt::Assign {
+            name,
+            value,
+            span,
+            doc,
+        })
     }
 
-    fn expression_statement(&mut self) -> Result<Stmt<T>, CalfErr> {
+    fn expression_statement(&mut self) -> Result<Stmt, CalfErr> {
         let expr = self.expression()?;
-        Ok(Stmt::Expr(expr))
+        let span = self.span(expr);
+        Ok(Stmt::Expr { expr, span })
     }
 
-    fn expression(&mut self) -> Result<Expr<T>, CalfErr> {
-        self.ternay()
+    fn expression(&mut self) -> Result<ExprId, CalfErr> {
+        self.operation(0)
     }
 
-    // Parsing a ternay expression:
-    //      cond_expr ? then_expr : else_expr
-    // Is equivalent to parsing two nested binary expressions:
-    //      cond_expr ? (then_expr : else_expr)
-    fn ternay(&mut self) -> Result<Expr<T>, CalfErr> {
-        let mut cond_expr = self.equality()?;
-        if self.is_token(TokenKind::Question, 0)? {
-            self.token().into_particle()?;
-            // Parse colon part of the expression
-            let right_expr = |_self: &mut Self| -> Result<Expr<T>, CalfErr> {
-                let mut then_expr = _self.ternay()?;
-                if _self.is_token(TokenKind::Colon, 0)? {
-                    let (colon_op, _) = _self.token().into_particle()?;
-                    let else_expr = _self.ternay()?;
-                    let then_pos = then_expr.pos.clone();
-                    then_expr = Expr::new(
-                        Syntagma::BinaryOp {
-                            op: colon_op,
-                            left_child: Box::new(then_expr),
-                            right_child: Box::new(else_expr),
-                        },
-                        then_pos,
-                    )
-                } else {
-                    return Err(CalfErr {
-                        message: "Expected a colon operator".into(),
-                        pos: then_expr.pos,
-                    });
+    // Parsing of operators by precedence climbing, with the precedences of the operator table. Only the
+    // operators with a precedence of at least `min_precedence` are parsed, the rest are left to the caller.
+    fn operation(&mut self, min_precedence: u8) -> Result<ExprId, CalfErr> {
+        let mut expr = self.unary()?;
+        while let Some(Token {
+            lexeme: Lexeme::Particle(op),
+            ..
+        }) = self.peek(0)
+        {
+            let op = *op;
+            let (precedence, assoc) = match operator::infix(op) {
+                Some((precedence, assoc)) if precedence >= min_precedence => (precedence, assoc),
+                _ => break,
+            };
+            self.token();
+            self.skip_newlines();
+            let syn = match op {
+                TokenKind::Question => self.ternary(expr, precedence)?,
+                TokenKind::Sharp => {
+                    if self.is_token(TokenKind::OpenClause, 0) {
+                        expr = self.set_indexation(expr)?;
+                        continue;
+                    }
+                    let right = self.call()?;
+                    Syntagma::BinaryOp {
+                        op,
+                        left_child: expr,
+                        right_child: right,
+                    }
                 }
-                Ok(then_expr)
-            }(self)?;
-
-            let pos_cond = cond_expr.pos.clone();
-
-            if let Syntagma::BinaryOp {
-                op: TokenKind::Colon,
-                left_child,
-                right_child,
-            } = right_expr.syn
-            {
-                cond_expr = Expr::new(
-                    Syntagma::TernaryOp {
-                        left_child: Box::new(cond_expr),
-                        mid_child: left_child,
-                        right_child,
-                    },
-                    pos_cond,
-                )
-            } else {
-                return Err(CalfErr {
-                    message: "Ternary operator '?' expects a colon operator".into(),
-                    pos: pos_cond,
-                });
-            }
-        }
-        Ok(cond_expr)
-    }
-
-    fn equality(&mut self) -> Result<Expr<T>, CalfErr> {
-        let mut expr = self.comparison()?;
-        while self.is_token(TokenKind::TwoEquals, 0)? || self.is_token(TokenKind::NotEqual, 0)? {
-            let (op, _) = self.token().into_particle()?;
-            let right = self.comparison()?;
-            let pos = expr.pos.clone();
-            expr = Expr::new(
-                Syntagma::BinaryOp {
-                    op,
-                    left_child: Box::new(expr),
-                    right_child: Box::new(right),
-                },
-                pos,
-            )
+                _ => {
+                    let min_precedence = match assoc {
+                        Assoc::Left => precedence + 1,
+                        Assoc::Right => precedence,
+                    };
+                    let right = self.operation(min_precedence)?;
+                    Syntagma::BinaryOp {
+                        op,
+                        left_child: expr,
+                        right_child: right,
+                    }
+                }
+            };
+            let span = self.span_from(expr);
+            expr = self.alloc(syn, span);
         }
         Ok(expr)
     }
 
-    fn comparison(&mut self) -> Result<Expr<T>, CalfErr> {
-        let mut expr = self.logic()?;
-        while self.is_token(TokenKind::GreaterThan, 0)?
-            || self.is_token(TokenKind::LesserThan, 0)?
-            || self.is_token(TokenKind::GtEqual, 0)?
-            || self.is_token(TokenKind::LtEqual, 0)?
-            || self.is_token(TokenKind::TwoAnds, 0)?
-            || self.is_token(TokenKind::TwoOrs, 0)?
-        {
-            let (op, _) = self.token().into_particle()?;
-            let right = self.logic()?;
-            let pos = expr.pos.clone();
-            expr = Expr::new(
-                Syntagma::BinaryOp {
-                    op,
-                    left_child: Box::new(expr),
-                    right_child: Box::new(right),
-                },
-                pos,
-            )
+    // Parsing the rest of a ternary expression, after the "?":
+    //      cond_expr ? then_expr : else_expr
+    fn ternary(&mut self, cond_expr: ExprId, precedence: u8) -> Result<Syntagma<T>, CalfErr> {
+        let then_expr = self.expression()?;
+        // The colon can be at the start of the next line
+        if self.continues_with(TokenKind::Colon) {
+            self.token().into_particle()?;
+            self.skip_newlines();
+        } else {
+            return Err(CalfErr {
+                code: ErrCode::MissingDelimiter,
+                message: "Ternary operator '?' expects a colon operator".into(),
+                span: self.span(then_expr),
+            });
         }
-        Ok(expr)
+        let else_expr = self.operation(precedence)?;
+        Ok(Syntagma::TernaryOp {
+            left_child: cond_expr,
+            mid_child: then_expr,
+            right_child: else_expr,
+        })
     }
 
-    fn logic(&mut self) -> Result<Expr<T>, CalfErr> {
-        let mut expr = self.term()?;
-        while self.is_token(TokenKind::And, 0)? || self.is_token(TokenKind::Or, 0)? {
-            let (op, _) = self.token().into_particle()?;
-            let right = self.term()?;
-            let pos = expr.pos.clone();
-            expr = Expr::new(
-                Syntagma::BinaryOp {
-                    op,
-                    left_child: Box::new(expr),
-                    right_child: Box::new(right),
-                },
-                pos,
-            )
+    fn unary(&mut self) -> Result<ExprId, CalfErr> {
+        let prefix = match self.peek(0) {
+            Some(Token {
+                lexeme: Lexeme::Particle(op),
+                ..
+            }) => operator::prefix(*op),
+            _ => None,
+        };
+        if let Some(precedence) = prefix {
+            let (op, op_span) = self.token().into_particle()?;
+            // Constant folding of negative literals, parsed with the sign to reach the minimum of the type
+            if op == TokenKind::Minus && self.is_literal_operand(precedence) {
+                return self.number(Some(op_span));
+            }
+            // Only the operators that bind tighter, like "#" in "-a#1"
+            let right = self.operation(precedence + 1)?;
+            let span = op_span.to(&self.last_span);
+            // Unary plus does nothing, like in "+INF"
+            if op == TokenKind::Plus {
+                self.exprs[right.index()].span = span;
+                return Ok(right);
+            }
+            return Ok(self.alloc(Syntagma::UnaryOp { op, child: right }, span));
         }
-        Ok(expr)
+        self.call()
     }
 
-    fn term(&mut self) -> Result<Expr<T>, CalfErr> {
-        let mut expr = self.factor()?;
-        while self.is_token(TokenKind::Plus, 0)? || self.is_token(TokenKind::Minus, 0)? {
-            let (op, _) = self.token().into_particle()?;
-            let right = self.factor()?;
-            let pos = expr.pos.clone();
-            expr = Expr::new(
-                Syntagma::BinaryOp {
-                    op,
-                    left_child: Box::new(expr),
-                    right_child: Box::new(right),
-                },
-                pos,
-            )
-        }
-        Ok(expr)
-    }
+    //TODO: parse "." operator
 
-    fn factor(&mut self) -> Result<Expr<T>, CalfErr> {
-        let mut expr = self.unary()?;
-        while self.is_token(TokenKind::Star, 0)?
-            || self.is_token(TokenKind::Slash, 0)?
-            || self.is_token(TokenKind::Percent, 0)?
-        {
-            let (op, _) = self.token().into_particle()?;
-            let right = self.unary()?;
-            let pos = expr.pos.clone();
-            expr = Expr::new(
-                Syntagma::BinaryOp {
-                    op,
-                    left_child: Box::new(expr),
-                    right_child: Box::new(right),
-                },
-                pos,
-            )
-        }
-        Ok(expr)
-    }
+    // "#" operator:
+    //      simple indexation: arr#i
+    //      slice indexation: arr#[i..j], arr#[..j], arr#[i..], arr#[..]
+    //      set indexation: arr#[i,j,k]
+    fn set_indexation(&mut self, vector: ExprId) -> Result<ExprId, CalfErr> {
+        let (_, clause_span) = self.token().into_particle()?; // consume "["
+        self.nesting += 1;
 
-    fn unary(&mut self) -> Result<Expr<T>, CalfErr> {
-        if self.is_token(TokenKind::Not, 0)? || self.is_token(TokenKind::Minus, 0)? {
-            let (op, _) = self.token().into_particle()?;
-            let right = self.unary()?;
-            let pos = right.pos.clone();
-            return Ok(Expr::new(
-                Syntagma::UnaryOp {
-                    op,
-                    child: Box::new(right),
-                },
-                pos,
-            ));
+        let start = if self.is_token(TokenKind::TwoDots, 0) {
+            None
+        } else if self.is_token(TokenKind::ClosingClause, 0) {
+            return Err(CalfErr {
+                code: ErrCode::UnexpectedToken,
+                message: "Set indexation requires at least one index".into(),
+                span: clause_span,
+            });
+        } else {
+            Some(self.expression()?)
+        };
+
+        // Slice indexation
+        if self.is_token(TokenKind::TwoDots, 0) {
+            self.token().into_particle()?; // consume ".."
+            let end = if self.is_token(TokenKind::ClosingClause, 0) {
+                None
+            } else {
+                Some(self.expression()?)
+            };
+            self.closing_clause(clause_span)?;
+            self.nesting -= 1;
+            let span = self.span_from(vector);
+            return Ok(self.alloc(Syntagma::Slice { vector, start, end }, span));
         }
-        self.indexation()
-    }
 
-    //TODO: parse "." operator
-    //TODO: parse set and slice indexations
-
-    // "#" operator
-    fn indexation(&mut self) -> Result<Expr<T>, CalfErr> {
-        let mut expr = self.call()?;
-        while self.is_token(TokenKind::Sharp, 0)? {
-            let (op, _) = self.token().into_particle()?;
-            let right = self.call()?;
-            let pos = expr.pos.clone();
-            expr = Expr::new(
-                Syntagma::BinaryOp {
-                    op,
-                    left_child: Box::new(expr),
-                    right_child: Box::new(right),
-                },
-                pos,
-            )
+        // Set indexation
+        let mut indexes: Vec<ExprId> = start.into_iter().collect();
+        loop {
+            if self.is_token(TokenKind::ClosingClause, 0) {
+                self.token().into_particle()?; // consume "]"
+                break;
+            }
+            if self.is_token(TokenKind::Comma, 0) {
+                self.token().into_particle()?; // consume ","
+                indexes.push(self.expression()?);
+            } else if self.ended() {
+                return Err(CalfErr {
+                    code: ErrCode::MissingDelimiter,
+                    message: "Unclosed set indexation, expected a closing clause".into(),
+                    span: clause_span,
+                });
+            } else {
+                let span = self.next_span();
+                return Err(CalfErr {
+                    code: ErrCode::MissingDelimiter,
+                    message: "Expecting a comma or a closing clause".into(),
+                    span,
+                });
+            }
         }
-        Ok(expr)
+        self.nesting -= 1;
+        let span = self.span_from(vector);
+        Ok(self.alloc(Syntagma::Gather { vector, indexes }, span))
     }
 
-    fn call(&mut self) -> Result<Expr<T>, CalfErr> {
-        if self.is_token(TokenKind::Ident, 0)? && self.is_token(TokenKind::OpenCurly, 1)? {
-            let (func, pos) = self.token().into_ident()?;
+    fn call(&mut self) -> Result<ExprId, CalfErr> {
+        if self.is_token(TokenKind::Ident, 0) && self.is_token(TokenKind::OpenCurly, 1) {
+            let (func, span) = self.token().into_ident()?;
             self.token().into_particle()?; // consume "{"
-            let mut args = vec![];
-            let mut expect_comma = false;
-            let mut expect_arg = true;
-            loop {
-                if self.is_token(TokenKind::ClosingCurly, 0)? {
-                    self.token().into_particle()?; // consume "}"
-                    break;
-                }
+            self.nesting += 1;
+            let args = self.separated(
+                TokenKind::ClosingCurly,
+                &span,
+                "Unclosed call, expected a closing curly bracket",
+                |parser| parser.expression(),
+                |parser, err| Some(parser.error_expr(err)),
+            )?;
+            self.nesting -= 1;
+            let span = span.to(&self.last_span);
+            return Ok(self.alloc(Syntagma::Call { func, args }, span));
+        }
+        self.lambda()
+    }
 
-                if expect_comma {
-                    if self.is_token(TokenKind::Comma, 0)? {
-                        self.token().into_particle()?; // consume ","
-                        expect_arg = true;
-                        expect_comma = false;
-                        continue;
+    fn lambda(&mut self) -> Result<ExprId, CalfErr> {
+        if self.is_ident("f", 0) && self.is_token(TokenKind::OpenParenth, 1) {
+            let (_, span) = self.token().into_ident()?; // consume "f"
+            self.token().into_particle()?; // consume "("
+            self.nesting += 1;
+            let params = self.separated(
+                TokenKind::ClosingParenth,
+                &span,
+                "Unclosed parameter list, expected a closing parenthesis",
+                |parser| {
+                    if parser.is_token(TokenKind::Ident, 0) {
+                        parser.token().into_ident().map(|(param, _)| param)
                     } else {
-                        let (_, pos) = self.token().into_parts()?;
-                        return Err(CalfErr {
-                            message: "Expecting a comma".into(),
-                            pos,
-                        });
+                        Err(CalfErr {
+                            code: ErrCode::UnexpectedToken,
+                            message: "Expecting a parameter".into(),
+                            span: parser.next_span(),
+                        })
                     }
-                } else if self.is_token(TokenKind::Comma, 0)? {
-                    let (_, pos) = self.token().into_parts()?;
-                    return Err(CalfErr {
-                        message: "Not expecting a comma".into(),
-                        pos,
-                    });
-                }
-
-                if expect_arg {
-                    let arg = self.expression()?;
-                    args.push(arg);
-                    expect_arg = false;
-                    expect_comma = true;
-                }
-            }
+                },
+                |parser, err| {
+                    parser.errors.push(err);
+                    None
+                },
+            )?;
+            self.nesting -= 1;
+            self.skip_newlines();
+            let body = self.expression()?;
 
-            return Ok(Expr::new(Syntagma::Call { func, args }, pos));
+            let span = span.to(&self.last_span);
+            return Ok(self.alloc(Syntagma::Lambda { params, body }, span));
         }
-        self.lambda()
+        self.primary()
     }
 
-    fn lambda(&mut self) -> Result<Expr<T>, CalfErr> {
-        if self.is_ident("f", 0)? && self.is_token(TokenKind::OpenParenth, 1)? {
-            let (_, pos) = self.token().into_ident()?; // consume "f"
-            self.token().into_particle()?; // consume "("
-
-            let mut params = vec![];
-            let mut expect_comma = false;
-            let mut expect_param = true;
+    /// Items separated by commas, until the `close` bracket. After an error in an item, `recover` can put
+    /// something in its place, and the parser skips to the next comma. If the bracket is not closed, it
+    /// fails with the `unclosed` message at the `open` span.
+    fn separated<Item>(
+        &mut self,
+        close: TokenKind,
+        open: &Span,
+        unclosed: &str,
+        mut item: impl FnMut(&mut Self) -> Result<Item, CalfErr>,
+        recover: impl Fn(&mut Self, CalfErr) -> Option<Item>,
+    ) -> Result<Vec<Item>, CalfErr> {
+        let stops = [TokenKind::Comma, close];
+        let mut items = vec![];
+        let mut expect_comma = false;
+        loop {
+            if self.is_token(close, 0) {
+                self.token().into_particle()?; // consume the closing bracket
+                return Ok(items);
+            }
 
-            loop {
-                if self.is_token(TokenKind::ClosingParenth, 0)? {
-                    self.token().into_particle()?; // consume ")"
-                    break;
-                }
+            // The code ends, or a bracket of another kind is closed
+            if self.ended() || self.is_closing(0) {
+                return Err(CalfErr {
+                    code: ErrCode::Unclosed,
+                    message: unclosed.into(),
+                    span: open.clone(),
+                });
+            }
 
-                if expect_comma {
-                    if self.is_token(TokenKind::Comma, 0)? {
-                        self.token().into_particle()?; // consume ","
-                        expect_param = true;
-                        expect_comma = false;
-                        continue;
-                    } else {
-                        let (_, pos) = self.token().into_parts()?;
-                        return Err(CalfErr {
-                            message: "Expecting a comma".into(),
-                            pos,
-                        });
-                    }
-                } else if self.is_token(TokenKind::Comma, 0)? {
-                    let (_, pos) = self.token().into_parts()?;
-                    return Err(CalfErr {
-                        message: "Not expecting a comma".into(),
-                        pos,
-                    });
+            if expect_comma {
+                if self.is_token(TokenKind::Comma, 0) {
+                    self.token().into_particle()?; // consume ","
+                    expect_comma = false;
+                    continue;
                 }
+                let span = self.next_span();
+                self.errors.push(CalfErr {
+                    code: ErrCode::MissingDelimiter,
+                    message: "Expecting a comma".into(),
+                    span,
+                });
+                self.synchronize(&stops);
+                continue;
+            } else if self.is_token(TokenKind::Comma, 0) {
+                let (_, span) = self.token().into_parts();
+                self.errors.push(CalfErr {
+                    code: ErrCode::UnexpectedToken,
+                    message: "Not expecting a comma".into(),
+                    span,
+                });
+                continue;
+            }
 
-                if expect_param {
-                    if self.is_token(TokenKind::Ident, 0)? {
-                        let (param, _) = self.token().into_ident()?;
-                        params.push(param);
-                        expect_param = false;
-                        expect_comma = true;
-                    } else {
-                        let (_, pos) = self.token().into_parts()?;
-                        return Err(CalfErr {
-                            message: "Expecting a parameter".into(),
-                            pos,
-                        });
-                    }
+            let nesting = self.nesting;
+            match item(self) {
+                Ok(item) => items.push(item),
+                Err(err) => {
+                    self.nesting = nesting;
+                    items.extend(recover(self, err));
+                    self.synchronize(&stops);
                 }
             }
-
-            let body = Box::new(self.expression()?);
-
-            return Ok(Expr::new(Syntagma::Lambda { params, body }, pos));
+            expect_comma = true;
         }
-        self.primary()
     }
 
-    fn primary(&mut self) -> Result<Expr<T>, CalfErr> {
+    fn primary(&mut self) -> Result<ExprId, CalfErr> {
+        // Token that couldn't be scanned, the error is already reported
+        if self.is_error(0) {
+            let (_, span) = self.token().into_parts();
+            return Ok(self.alloc(Syntagma::Error, span));
+        }
         // Number literal
-        if self.is_token(TokenKind::Int, 0)? || self.is_token(TokenKind::Float, 0)? {
-            let (n, pos) = self.token().into_number()?;
-            let expr = Expr::new(Syntagma::Number(n), pos);
-            return Ok(expr);
+        if self.is_num(0) {
+            return self.number(None);
         }
         // Identifier
-        if self.is_token(TokenKind::Ident, 0)? {
-            let (id, pos) = self.token().into_ident()?;
+        if self.is_token(TokenKind::Ident, 0) {
+            let (id, span) = self.token().into_ident()?;
             if id == "f" {
                 return Err(CalfErr {
+                    code: ErrCode::ReservedWord,
                     message: "'f' is a reserved word".into(),
-                    pos,
+                    span,
                 });
             }
-            let expr = Expr::new(Syntagma::Identifier(id), pos);
+            let expr = self.alloc(Syntagma::Identifier(id), span);
             return Ok(expr);
         }
         // Group
-        if self.is_token(TokenKind::OpenParenth, 0)? {
-            self.token().into_particle()?; // consume "("
+        if self.is_token(TokenKind::OpenParenth, 0) {
+            let (_, open_span) = self.token().into_particle()?; // consume "("
+            self.nesting += 1;
             let expr = self.expression()?;
-            if self.is_token(TokenKind::ClosingParenth, 0)? {
+            if self.is_token(TokenKind::ClosingParenth, 0) {
                 self.token().into_particle()?; // consume ")"
             } else {
-                let (_, pos) = self.token().into_parts()?;
+                let span = self.next_span();
                 return Err(CalfErr {
+                    code: ErrCode::MissingDelimiter,
                     message: "Expected a closing parenthesis after expression".into(),
-                    pos,
+                    span,
                 });
             }
-            let pos = expr.pos.clone();
-            let expr = Expr::new(
-                Syntagma::Group {
-                    expr: Box::new(expr),
-                },
-                pos,
-            );
+            self.nesting -= 1;
+            let span = open_span.to(&self.last_span);
+            let expr = self.alloc(Syntagma::Group { expr }, span);
             return Ok(expr);
         }
         // List
-        if self.is_token(TokenKind::OpenClause, 0)? {
-            //TODO: parse list literals: array and range
-            // array form: [a,b,c,d,e] --> values can be expressions
-            // range form: [V;S;I] or [V;S] (I = 0) --> V = value (expression), S = size (integer), I = increment (expression)
-            if self.is_token(TokenKind::ClosingClause, 1)? {
-                //TODO: empty list (is this necessary? or the array form can handle it, even if si the last expression)
-            } else if self.is_num(1)?
-                && self.is_token(TokenKind::Semicolon, 2)?
-                && self.is_token(TokenKind::Int, 3)?
-                && self.is_token(TokenKind::Semicolon, 4)?
-                && self.is_num(5)?
-                && self.is_token(TokenKind::ClosingClause, 6)?
-            {
-                //TODO: complete range form: [V;S;I]
-            } else if self.is_num(1)?
-                && self.is_token(TokenKind::Semicolon, 2)?
-                && self.is_token(TokenKind::Int, 3)?
-                && self.is_token(TokenKind::ClosingClause, 4)?
-            {
-                //TODO: short range form: [V;S]
-            } else {
-                //TODO: try to parse the array form
-            }
+        if self.is_token(TokenKind::OpenClause, 0) {
+            return self.list();
         }
         //TODO: check the next token and see if we can provide a more specific error message
         // If we are here, something is badly formed
         Err(CalfErr {
+            code: ErrCode::UnexpectedToken,
             message: "Couldn't parse a valid expression".into(),
-            pos: self.lexer.pos(),
+            span: self.next_span(),
         })
     }
 
-    fn is_token(&mut self, ttype: TokenKind, offset: usize) -> Result<bool, CalfErr> {
-        // Get missing tokens from Lexer
-        if offset >= self.tokens.len() {
-            let missing = offset - self.tokens.len() + 1;
-            for _ in 0..missing {
-                let mut token = self.lexer.scan_token()?;
-                // Skip None tokens (newlines and comments)
-                while let Lexeme::None = token.lexeme {
-                    token = self.lexer.scan_token()?;
+    /// Number literal, negated if `minus` is the span of a minus sign before it. Literals out of the range
+    /// of the type are reported, and replaced by an error expression.
+    fn number(&mut self, minus: Option<Span>) -> Result<ExprId, CalfErr> {
+        let (lexeme, span) = self.token().into_parts();
+        let negative = minus.is_some();
+        let span = match minus {
+            Some(minus) => minus.to(&span),
+            None => span,
+        };
+        let number = match lexeme {
+            Lexeme::Number(n) if negative => Ok(n.neg()),
+            Lexeme::Number(n) => Ok(n),
+            Lexeme::Int(text) => int_literal(&text, negative),
+            _ => {
+                return Err(CalfErr {
+                    code: ErrCode::TypeMismatch,
+                    message: "Expected a number".into(),
+                    span,
+                })
+            }
+        };
+        match number {
+            Ok(n) => Ok(self.alloc(Syntagma::Number(n), span)),
+            Err(message) => {
+                self.errors.push(CalfErr {
+                    code: ErrCode::InvalidNumber,
+                    message,
+                    span: span.clone(),
+                });
+                Ok(self.alloc(Syntagma::Error, span))
+            }
+        }
+    }
+
+    // List literals:
+    //      array form: [a,b,c,d,e] --> values can be expressions
+    //      range form: [V;S;I] or [V;S] (I = 0) --> V = value (expression), S = size (integer), I = increment (expression)
+    fn list(&mut self) -> Result<ExprId, CalfErr> {
+        let (_, open_span) = self.token().into_particle()?; // consume "["
+        self.nesting += 1;
+
+        // Empty list
+        if self.is_token(TokenKind::ClosingClause, 0) {
+            let (_, span) = self.token().into_particle()?; // consume "]"
+            self.nesting -= 1;
+            return Ok(self.alloc(Syntagma::Vector { values: vec![] }, open_span.to(&span)));
+        }
+
+        let first = self.expression()?;
+
+        // Range form
+        if self.is_token(TokenKind::Semicolon, 0) {
+            self.token().into_particle()?; // consume ";"
+            if !self.is_token(TokenKind::Int, 0) {
+                let span = self.next_span();
+                return Err(CalfErr {
+                    code: ErrCode::UnexpectedToken,
+                    message: "Expected an integer size in range".into(),
+                    span,
+                });
+            }
+            let (len, _) = self.token().into_number()?;
+            let step = if self.is_token(TokenKind::Semicolon, 0) {
+                self.token().into_particle()?; // consume ";"
+                Some(self.expression()?)
+            } else {
+                None
+            };
+            self.closing_clause(open_span.clone())?;
+            self.nesting -= 1;
+            return Ok(self.alloc(
+                Syntagma::Range {
+                    init: first,
+                    len,
+                    step,
+                },
+                open_span.to(&self.last_span),
+            ));
+        }
+
+        // Array form
+        let mut values = vec![first];
+        loop {
+            if self.is_token(TokenKind::ClosingClause, 0) {
+                self.token().into_particle()?; // consume "]"
+                break;
+            }
+            if self.is_token(TokenKind::Comma, 0) {
+                self.token().into_particle()?; // consume ","
+                values.push(self.expression()?);
+            } else if self.ended() {
+                return Err(CalfErr {
+                    code: ErrCode::MissingDelimiter,
+                    message: "Unclosed list, expected a closing clause".into(),
+                    span: open_span,
+                });
+            } else {
+                let span = self.next_span();
+                return Err(CalfErr {
+                    code: ErrCode::MissingDelimiter,
+                    message: "Expecting a comma or a closing clause".into(),
+                    span,
+                });
+            }
+        }
+        self.nesting -= 1;
+        let span = open_span.to(&self.last_span);
+        Ok(self.alloc(Syntagma::Vector { values }, span))
+    }
+
+    // Consume the "]" that closes a list or indexation opened at `open_span`.
+    fn closing_clause(&mut self, open_span: Span) -> Result<(), CalfErr> {
+        if self.is_token(TokenKind::ClosingClause, 0) {
+            self.token().into_particle()?; // consume "]"
+            Ok(())
+        } else if self.ended() {
+            Err(CalfErr {
+                code: ErrCode::MissingDelimiter,
+                message: "Unclosed clause, expected a closing clause".into(),
+                span: open_span,
+            })
+        } else {
+            let span = self.next_span();
+            Err(CalfErr {
+                code: ErrCode::MissingDelimiter,
+                message: "Expected a closing clause".into(),
+                span,
+            })
+        }
+    }
+
+    /// Get tokens from the lexer until there is one at the specified offset, or the code ends.
+    fn fill(&mut self, offset: usize) {
+        while offset >= self.tokens.len() {
+            let token = match self.lexer.next() {
+                Some(Ok(token)) => token,
+                // End of the code
+                None => break,
+                // An unclosed comment takes the rest of the code, there is nothing else to parse
+                Some(Err(err)) if err.code == ErrCode::UnclosedComment => {
+                    self.errors.push(err);
+                    continue;
                 }
-                // End Of File token, end getting tokens
-                if let Lexeme::EOF = token.lexeme {
-                    break;
+                // Keep the place of the invalid lexeme with an error token
+                Some(Err(err)) => {
+                    let token = Token::new(Lexeme::Error, err.span.clone());
+                    self.errors.push(err);
+                    token
                 }
+            };
+            match token.lexeme {
+                // Skip None tokens (comments)
+                Lexeme::None => continue,
+                // Keep doc comments apart, they are attached to the next statement
+                Lexeme::Doc(text) => self.docs.push_back((token.span.byte_offset, text)),
+                _ => self.tokens.push_back(token),
+            }
+        }
+    }
 
-                self.tokens.push_back(token);
+    /// Position in the queue of the token at `offset`, not counting the newlines that are ignored.
+    fn index(&mut self, offset: usize) -> Option<usize> {
+        let mut remaining = offset;
+        let mut i = 0;
+        loop {
+            self.fill(i);
+            let token = self.tokens.get(i)?;
+            let ignored = self.nesting > 0 && token.lexeme == Lexeme::Particle(TokenKind::EOL);
+            if !ignored {
+                if remaining == 0 {
+                    return Some(i);
+                }
+                remaining -= 1;
             }
+            i += 1;
         }
+    }
+
+    fn peek(&mut self, offset: usize) -> Option<&Token<T>> {
+        let i = self.index(offset)?;
+        self.tokens.get(i)
+    }
+
+    fn is_token(&mut self, ttype: TokenKind, offset: usize) -> bool {
         // Check if token exist at the specified offset
-        if let Some(token) = self.tokens.get(offset) {
-            Ok(match token.lexeme {
-                Lexeme::Number(_) => ttype == TokenKind::Int || ttype == TokenKind::Float,
+        if let Some(token) = self.peek(offset) {
+            match token.lexeme {
+                Lexeme::Int(_) => ttype == TokenKind::Int,
+                // Float literals, NAN and INF
+                Lexeme::Number(_) => ttype == TokenKind::Float,
                 Lexeme::Ident(_) => ttype == TokenKind::Ident,
                 Lexeme::Particle(tt) => ttype == tt,
                 _ => false,
+            }
+        } else {
+            false
+        }
+    }
+
+    fn is_error(&mut self, offset: usize) -> bool {
+        matches!(
+            self.peek(offset),
+            Some(Token {
+                lexeme: Lexeme::Error,
+                ..
             })
+        )
+    }
+
+    /// Check if the operand of a prefix operator is only a number literal, like in "-1 + 2", but not in
+    /// "-2 ^ 2", where the literal is the operand of an operator that binds tighter.
+    fn is_literal_operand(&mut self, precedence: u8) -> bool {
+        if !self.is_num(0) {
+            return false;
+        }
+        match self.peek(1) {
+            Some(Token {
+                lexeme: Lexeme::Particle(op),
+                ..
+            }) => !matches!(operator::infix(*op), Some((infix, _)) if infix > precedence),
+            _ => true,
+        }
+    }
+
+    fn is_closing(&mut self, offset: usize) -> bool {
+        self.is_token(TokenKind::ClosingParenth, offset)
+            || self.is_token(TokenKind::ClosingClause, offset)
+            || self.is_token(TokenKind::ClosingCurly, offset)
+    }
+
+    fn is_num(&mut self, offset: usize) -> bool {
+        self.is_token(TokenKind::Int, offset) || self.is_token(TokenKind::Float, offset)
+    }
+
+    fn is_ident(&mut self, ident: &str, offset: usize) -> bool {
+        if let Some(Token {
+            lexeme: Lexeme::Ident(lexeme_ident),
+            ..
+        }) = self.peek(offset)
+        {
+            return ident == lexeme_ident;
+        }
+        false
+    }
+
+    /// Consume the next token. When the code has ended, an EOF token is returned.
+    fn token(&mut self) -> Token<T> {
+        if let Some(i) = self.index(0) {
+            // Drop the ignored newlines
+            self.tokens.drain(..i);
+        }
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.consumed += 1;
+                self.last_span = token.span.clone();
+                token
+            }
+            None => Token::new(Lexeme::EOF, self.lexer.span()),
+        }
+    }
+
+    /// Skip newlines, when a statement continues in the next line.
+    fn skip_newlines(&mut self) {
+        while self.is_token(TokenKind::EOL, 0) {
+            self.token();
+        }
+    }
+
+    /// Check if the next token, ignoring newlines, is of the specified type. If it is, the newlines are skipped.
+    fn continues_with(&mut self, ttype: TokenKind) -> bool {
+        let mut offset = 0;
+        while self.is_token(TokenKind::EOL, offset) {
+            offset += 1;
+        }
+        if self.is_token(ttype, offset) {
+            self.skip_newlines();
+            true
         } else {
-            Ok(false)
+            false
         }
     }
 
-    fn is_num(&mut self, offset: usize) -> Result<bool, CalfErr> {
-        Ok(self.is_token(TokenKind::Int, offset)? || self.is_token(TokenKind::Float, offset)?)
+    /// Skip tokens until one of `stops` is found outside of any nested group. It also stops when the code
+    /// ends, or when a group that was opened before is closed.
+    fn synchronize(&mut self, stops: &[TokenKind]) {
+        let mut depth = 0;
+        loop {
+            match self.peek(0).map(|token| &token.lexeme) {
+                Some(Lexeme::Particle(kind)) if depth == 0 && stops.contains(kind) => return,
+                Some(Lexeme::Particle(
+                    TokenKind::OpenParenth | TokenKind::OpenClause | TokenKind::OpenCurly,
+                )) => depth += 1,
+                Some(Lexeme::Particle(
+                    TokenKind::ClosingParenth | TokenKind::ClosingClause | TokenKind::ClosingCurly,
+                )) => {
+                    if depth == 0 {
+                        return;
+                    }
+                    depth -= 1;
+                }
+                Some(_) => {}
+                None => return,
+            }
+            self.token();
+        }
     }
 
-    fn is_ident(&mut self, ident: &str, offset: usize) -> Result<bool, CalfErr> {
-        if self.is_token(TokenKind::Ident, offset)? {
-            if let Lexeme::Ident(lexeme_ident) = &self.tokens[offset].lexeme {
-                return Ok(ident == lexeme_ident);
+    /// Skip the remaining tokens of the current statement, until the end of the line or a `;` outside of brackets.
+    fn synchronize_stmt(&mut self) {
+        self.nesting = 0;
+        let mut depth: usize = 0;
+        loop {
+            match self.peek(0).map(|token| &token.lexeme) {
+                Some(Lexeme::Particle(TokenKind::EOL)) | None => break,
+                Some(Lexeme::Particle(TokenKind::Semicolon)) if depth == 0 => break,
+                Some(Lexeme::Particle(
+                    TokenKind::OpenParenth | TokenKind::OpenClause | TokenKind::OpenCurly,
+                )) => depth += 1,
+                Some(Lexeme::Particle(
+                    TokenKind::ClosingParenth | TokenKind::ClosingClause | TokenKind::ClosingCurly,
+                )) => depth = depth.saturating_sub(1),
+                Some(_) => {}
             }
+            self.token();
+        }
+    }
+
+    /// Collect a syntax error and create an expression in its place.
+    fn error_expr(&mut self, err: CalfErr) -> ExprId {
+        let span = err.span.clone();
+        self.errors.push(err);
+        self.alloc(Syntagma::Error, span)
+    }
+
+    pub fn ended(&mut self) -> bool {
+        self.peek(0).is_none()
+    }
+
+    /// Take the arena with all the parsed expressions, and the syntax errors sorted by position.
+    pub fn finish(mut self) -> (Vec<Expr<T>>, Vec<CalfErr>) {
+        self.errors.sort_by_key(|err| err.span.byte_offset);
+        (self.exprs, self.errors)
+    }
+
+    /// Store an expression in the arena.
+    fn alloc(&mut self, syn: Syntagma<T>, span: Span) -> ExprId {
+        self.exprs.push(Expr::new(syn, span));
+        ExprId::new(self.exprs.len() - 1)
+    }
+
+    /// Span of the next token, or the end of the code.
+    fn next_span(&mut self) -> Span {
+        match self.peek(0) {
+            Some(token) => token.span.clone(),
+            None => self.lexer.span(),
         }
-        Ok(false)
     }
 
-    fn token(&mut self) -> Option<Token<T>> {
-        self.tokens.pop_front()
+    fn span(&self, id: ExprId) -> Span {
+        self.exprs[id.index()].span.clone()
+    }
+
+    /// Span from the start of an expression to the last consumed token.
+    fn span_from(&self, id: ExprId) -> Span {
+        self.span(id).to(&self.last_span)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::Ast,
+        common::{ErrCode, Pos},
+        parser::{Stmt, Syntagma},
+    };
+    use alloc::vec::Vec;
+
+    /// Code and start position of the syntax errors.
+    fn errors(code: &str) -> Vec<(ErrCode, Pos)> {
+        let (_, errors) = Ast::<f64>::parse(code);
+        errors
+            .into_iter()
+            .map(|err| (err.code, err.span.start))
+            .collect()
+    }
+
+    #[test]
+    fn all_errors_in_one_pass() {
+        let code = "a = 1 * (2 + 3\n\
+                    b = foo{1, , 2}\n\
+                    c = 3 $ 4\n\
+                    d = f(x y) x\n\
+                    e = [1; 2.5]\n\
+                    ok = 5\n\
+                    foo{1 2]";
+        let (ast, errors) = Ast::<f64>::parse(code);
+        let errors: Vec<_> = errors
+            .into_iter()
+            .map(|err| (err.code, err.span.start))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                // The newline is ignored inside the parenthesis
+                (ErrCode::MissingDelimiter, Pos::new(1, 0)),
+                (ErrCode::UnexpectedToken, Pos::new(1, 11)),
+                (ErrCode::UnrecognizedLexeme, Pos::new(2, 6)),
+                (ErrCode::MissingDelimiter, Pos::new(3, 8)),
+                (ErrCode::UnexpectedToken, Pos::new(4, 8)),
+                (ErrCode::Unclosed, Pos::new(6, 0)),
+                (ErrCode::MissingDelimiter, Pos::new(6, 6)),
+            ]
+        );
+
+        // Every statement is kept, with errors in place of the broken expressions
+        let names: Vec<_> = ast
+            .statements
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::Assign { name, .. } => name.as_str(),
+                Stmt::Expr { .. } => "",
+            })
+            .collect();
+        assert_eq!(names, ["a", "b", "c", "d", "e", "ok", ""]);
+        let value = |i: usize| match &ast.statements[i] {
+            Stmt::Assign { value, .. } => &ast[*value].syn,
+            Stmt::Expr { expr, .. } => &ast[*expr].syn,
+        };
+        assert!(matches!(value(0), Syntagma::Error));
+        assert!(matches!(value(1), Syntagma::Call { args, .. } if args.len() == 2));
+        assert!(matches!(value(2), Syntagma::Number(n) if *n == 3.0));
+        assert!(matches!(value(3), Syntagma::Lambda { params, .. } if params == &["x"]));
+        assert!(matches!(value(4), Syntagma::Error));
+        assert!(matches!(value(5), Syntagma::Number(n) if *n == 5.0));
+        assert!(matches!(value(6), Syntagma::Error));
+    }
+
+    #[test]
+    fn errors_in_call_arguments() {
+        let code = "x = foo{1, $, 2}";
+        let (ast, errors) = Ast::<f64>::parse(code);
+        assert_eq!(errors.len(), 1);
+        let Stmt::Assign { value, .. } = &ast.statements[0] else {
+            panic!("Expected an assignment");
+        };
+        let Syntagma::Call { args, .. } = &ast[*value].syn else {
+            panic!("Expected a call");
+        };
+        assert!(matches!(ast[args[1]].syn, Syntagma::Error));
+    }
+
+    #[test]
+    fn unclosed_brackets() {
+        // Reported at the opening, once
+        for (code, col) in [
+            ("x = foo{1, 2", 4),
+            ("x = foo{1, 2]", 4),
+            ("x = foo{1, ]", 4),
+            ("g = f(a, b]", 4),
+            ("g = f(a, b", 4),
+        ] {
+            assert_eq!(
+                errors(code),
+                [(ErrCode::Unclosed, Pos::new(0, col))],
+                "{}",
+                code
+            );
+        }
+        assert_eq!(
+            errors("foo{1, 2\ny = 1"),
+            [
+                (ErrCode::Unclosed, Pos::new(0, 0)),
+                (ErrCode::MissingDelimiter, Pos::new(1, 0)),
+            ]
+        );
     }
 
-    pub fn ended(&self) -> bool {
-        self.tokens.len() == 0
+    #[test]
+    fn one_error_per_token() {
+        assert_eq!(
+            errors("a = 1 $ 2"),
+            [(ErrCode::UnrecognizedLexeme, Pos::new(0, 6))]
+        );
+        assert_eq!(
+            errors("a = 1 2"),
+            [(ErrCode::MissingSeparator, Pos::new(0, 6))]
+        );
     }
 }
@@ -1,22 +1,379 @@
-use crate::{common::CalfErr, parser::Stmt};
-use alloc::string::String;
-use hashbrown::HashMap;
+use crate::{
+    ast::Ast,
+    common::{CalfErr, ErrCode, Span},
+    host::Registry,
+    lexer::TokenKind,
+    number::CalfNumber,
+    parser::{ExprId, Stmt, Syntagma},
+    value::bitwise_error,
+};
+use alloc::{string::String, vec::Vec};
+use hashbrown::{HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// Interface of a program: the inputs it expects from the host, and the names it defines.
+pub struct Interface {
+    /// Free variables, in order of first use. They must be defined before running the program.
+    pub inputs: Vec<String>,
+    /// Names assigned by the program, in order of first assignment.
+    pub definitions: Vec<Definition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Name assigned by a program. If it's assigned more than once, the last assignment is described.
+pub struct Definition {
+    pub name: String,
+    /// Parameters, if the assigned value is a function.
+    pub params: Option<Vec<String>>,
+    /// Documentation, from the doc comments of the assignment.
+    pub doc: Option<String>,
+    /// Span of the assignment.
+    pub span: Span,
+}
 
 struct Symbol {
     stype: SymbolType,
-    //TODO: other necessary stuff
 }
 
+#[derive(Clone, Copy, PartialEq)]
 enum SymbolType {
-    Function,
+    /// Named function and its number of parameters.
+    Function(usize),
     Variable,
+    /// Lambda parameter, its type is unknown until the function is called.
+    Parameter,
+    /// Host function and its number of parameters.
+    Host(usize),
+}
+
+/// Symbol table with a global scope and, while checking a lambda body, the scope of its parameters.
+struct SymbolTable<'a, T> {
+    ast: &'a Ast<T>,
+    host: &'a Registry<T>,
+    globals: HashMap<&'a str, Symbol>,
+    locals: Option<HashMap<&'a str, Symbol>>,
+    /// All the names assigned somewhere in the program.
+    assigned: HashSet<&'a str>,
+    /// Names whose last assignment is a lambda, with its number of parameters.
+    functions: HashMap<&'a str, usize>,
+    /// Free variables found, in order of first use.
+    inputs: Vec<&'a str>,
+}
+
+/// Check a program and describe its interface.
+pub fn check<T: CalfNumber>(ast: &Ast<T>, host: &Registry<T>) -> Result<Interface, CalfErr> {
+    let mut table = SymbolTable::new(ast, host);
+    for stmt in ast.statements.iter() {
+        match stmt {
+            Stmt::Assign {
+                name, value, span, ..
+            } => {
+                if host.find(name).is_some() {
+                    return Err(CalfErr {
+                        code: ErrCode::HostRedefinition,
+                        message: format!("'{}' is a host function, it can't be redefined", name),
+                        span: span.clone(),
+                    });
+                }
+                if let Syntagma::Lambda { params, .. } = &ast[*value].syn {
+                    // Define it before checking the body, to allow recursion
+                    table.define(name, SymbolType::Function(params.len()));
+                    table.check_expr(*value)?;
+                } else {
+                    table.check_expr(*value)?;
+                    table.define(name, SymbolType::Variable);
+                }
+            }
+            Stmt::Expr { expr, .. } => table.check_expr(*expr)?,
+        }
+    }
+    Ok(table.interface())
+}
+
+impl<'a, T: CalfNumber> SymbolTable<'a, T> {
+    fn new(ast: &'a Ast<T>, host: &'a Registry<T>) -> Self {
+        let mut assigned = HashSet::new();
+        let mut functions = HashMap::new();
+        for stmt in ast.statements.iter() {
+            if let Stmt::Assign { name, value, .. } = stmt {
+                assigned.insert(name.as_str());
+                // Only the names that are still functions after the last assignment
+                if let Syntagma::Lambda { params, .. } = &ast[*value].syn {
+                    functions.insert(name.as_str(), params.len());
+                } else {
+                    functions.remove(name.as_str());
+                }
+            }
+        }
+        Self {
+            ast,
+            host,
+            globals: Default::default(),
+            locals: None,
+            assigned,
+            functions,
+            inputs: Default::default(),
+        }
+    }
+
+    fn interface(self) -> Interface {
+        let mut definitions: Vec<Definition> = Vec::new();
+        for stmt in self.ast.statements.iter() {
+            if let Stmt::Assign {
+                name,
+                value,
+                span,
+                doc,
+            } = stmt
+            {
+                let definition = Definition {
+                    name: name.clone(),
+                    params: match &self.ast[*value].syn {
+                        Syntagma::Lambda { params, .. } => Some(params.clone()),
+                        _ => None,
+                    },
+                    doc: doc.clone(),
+                    span: span.clone(),
+                };
+                match definitions.iter_mut().find(|d| d.name == *name) {
+                    Some(previous) => *previous = definition,
+                    None => definitions.push(definition),
+                }
+            }
+        }
+        Interface {
+            inputs: self.inputs.into_iter().map(String::from).collect(),
+            definitions,
+        }
+    }
+
+    fn define(&mut self, name: &'a str, stype: SymbolType) {
+        self.globals.insert(name, Symbol { stype });
+    }
+
+    fn check_expr(&mut self, id: ExprId) -> Result<(), CalfErr> {
+        let ast = self.ast;
+        let expr = &ast[id];
+        match &expr.syn {
+            Syntagma::Identifier(id) => self.check_identifier(id, &expr.span),
+            Syntagma::Call { func, args } => {
+                self.check_call(func, args.len(), &expr.span)?;
+                self.check_exprs(args)
+            }
+            Syntagma::Lambda { params, body } => {
+                if self.locals.is_some() {
+                    return Err(CalfErr {
+                        code: ErrCode::NestedLambda,
+                        message: "A lambda can't contain another lambda".into(),
+                        span: expr.span.clone(),
+                    });
+                }
+                let mut locals = HashMap::new();
+                for param in params {
+                    let symbol = Symbol {
+                        stype: SymbolType::Parameter,
+                    };
+                    if locals.insert(param.as_str(), symbol).is_some() {
+                        return Err(CalfErr {
+                            code: ErrCode::DuplicatedParameter,
+                            message: format!("Duplicated parameter '{}'", param),
+                            span: expr.span.clone(),
+                        });
+                    }
+                }
+                self.locals = Some(locals);
+                let result = self.check_expr(*body);
+                self.locals = None;
+                result
+            }
+            Syntagma::Range { len, .. } if len.to_index().is_none() => Err(CalfErr {
+                code: ErrCode::InvalidRange,
+                message: "Range size must be a non negative integer".into(),
+                span: expr.span.clone(),
+            }),
+            // Floats don't have bits
+            Syntagma::BinaryOp {
+                op: TokenKind::And | TokenKind::Or,
+                ..
+            } if T::zero().bit_and(T::zero()).is_none() => Err(bitwise_error::<T>(&expr.span)),
+            syn => self.check_exprs(&syn.children()),
+        }
+    }
+
+    fn check_exprs(&mut self, ids: &[ExprId]) -> Result<(), CalfErr> {
+        for id in ids {
+            self.check_expr(*id)?;
+        }
+        Ok(())
+    }
+
+    fn check_identifier(&mut self, id: &'a str, span: &Span) -> Result<(), CalfErr> {
+        match self.resolve(id, span)? {
+            Some(SymbolType::Host(_)) => Err(CalfErr {
+                code: ErrCode::NotAFunction,
+                message: format!("Host function '{}' can only be called", id),
+                span: span.clone(),
+            }),
+            // Free variables are inputs of the program
+            None => {
+                if !self.inputs.contains(&id) {
+                    self.inputs.push(id);
+                }
+                Ok(())
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
+    fn check_call(&self, func: &str, num_args: usize, span: &Span) -> Result<(), CalfErr> {
+        match self.resolve(func, span)? {
+            Some(SymbolType::Function(num_params)) | Some(SymbolType::Host(num_params))
+                if num_params != num_args =>
+            {
+                Err(CalfErr {
+                    code: ErrCode::ArityMismatch,
+                    message: format!(
+                        "Function '{}' expects {} arguments, got {}",
+                        func, num_params, num_args
+                    ),
+                    span: span.clone(),
+                })
+            }
+            Some(SymbolType::Function(_))
+            | Some(SymbolType::Host(_))
+            | Some(SymbolType::Parameter) => Ok(()),
+            Some(SymbolType::Variable) => Err(CalfErr {
+                code: ErrCode::NotAFunction,
+                message: format!("'{}' is a variable, it can't be used as a function", func),
+                span: span.clone(),
+            }),
+            None => Err(CalfErr {
+                code: ErrCode::UndefinedSymbol,
+                message: format!("Undefined function '{}'", func),
+                span: span.clone(),
+            }),
+        }
+    }
+
+    /// Find the type of a symbol, or `None` if it's a free variable.
+    fn resolve(&self, id: &str, span: &Span) -> Result<Option<SymbolType>, CalfErr> {
+        if let Some(locals) = &self.locals {
+            if let Some(symbol) = locals.get(id) {
+                return Ok(Some(symbol.stype));
+            }
+            // Inside a lambda, only named functions can be used, even if they are defined later
+            if let Some(num_params) = self.functions.get(id) {
+                return Ok(Some(SymbolType::Function(*num_params)));
+            }
+            if let Some(host) = self.host_function(id) {
+                return Ok(Some(host));
+            }
+            return Err(CalfErr {
+                code: ErrCode::CapturedVariable,
+                message: format!(
+                    "Functions can't capture external variables, '{}' is not a parameter",
+                    id
+                ),
+                span: span.clone(),
+            });
+        }
+        if let Some(symbol) = self.globals.get(id) {
+            Ok(Some(symbol.stype))
+        } else if self.assigned.contains(id) {
+            Err(CalfErr {
+                code: ErrCode::UndefinedSymbol,
+                message: format!("Symbol '{}' used before its definition", id),
+                span: span.clone(),
+            })
+        } else {
+            Ok(self.host_function(id))
+        }
+    }
+
+    fn host_function(&self, id: &str) -> Option<SymbolType> {
+        self.host
+            .find(id)
+            .and_then(|index| self.host.get(index))
+            .map(|function| SymbolType::Host(function.arity))
+    }
 }
 
-pub fn check<T>(_statements: &[Stmt<T>]) -> Result<(), CalfErr> {
-    let _symbols: HashMap<String, Symbol>;
-    //TODO: check no lambda contains another lambda
-    //TODO: check no named funcion captures extern variables
-    //TODO: check symbol usage, don't use undefined variables
-    //TODO: check variable types, don't use as a function something that is data
-    Ok(())
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bytecode::Program, common::Pos, eval::Evaluator, vm::Vm};
+
+    /// Code and position of the error found by the checks.
+    fn check_error(code: &str, host: &Registry<f64>) -> (ErrCode, Pos) {
+        let (ast, errors) = Ast::<f64>::parse(code);
+        assert!(errors.is_empty(), "{:?} in {}", errors, code);
+        let err = ast.interface_with(host).unwrap_err();
+        (err.code, err.span.start)
+    }
+
+    #[test]
+    fn error_codes() {
+        let mut host = Registry::new();
+        host.register_scalar("sq", 1, |args: &[f64]| args[0] * args[0]);
+        let cases = [
+            ("a = foo{1}", ErrCode::UndefinedSymbol, Pos::new(0, 4)),
+            (
+                "k = 2\ng = f(a) a * k",
+                ErrCode::CapturedVariable,
+                Pos::new(1, 13),
+            ),
+            ("g = f(a) f(b) a + b", ErrCode::NestedLambda, Pos::new(0, 9)),
+            (
+                "g = f(a, b, a) a + b",
+                ErrCode::DuplicatedParameter,
+                Pos::new(0, 4),
+            ),
+            (
+                "g = f(a, b) a + b\ng{1}",
+                ErrCode::ArityMismatch,
+                Pos::new(1, 0),
+            ),
+            ("sq{1, 2}", ErrCode::ArityMismatch, Pos::new(0, 0)),
+            ("k = 2\nk{1}", ErrCode::NotAFunction, Pos::new(1, 0)),
+            ("a = sq", ErrCode::NotAFunction, Pos::new(0, 4)),
+            ("a = b + 1\nb = 2", ErrCode::UndefinedSymbol, Pos::new(0, 4)),
+            ("sq = f(a) a * a", ErrCode::HostRedefinition, Pos::new(0, 0)),
+        ];
+        for (code, err, pos) in cases {
+            assert_eq!(check_error(code, &host), (err, pos), "{}", code);
+        }
+    }
+
+    #[test]
+    fn interface() {
+        let (ast, _) = Ast::<f64>::parse("/// Scaled input\ny = x * k\ng = f(a) a + 1\ny = g{x}");
+        let interface = ast.interface().unwrap();
+        assert_eq!(interface.inputs, ["x", "k"]);
+        let names: Vec<_> = interface
+            .definitions
+            .iter()
+            .map(|d| d.name.as_str())
+            .collect();
+        assert_eq!(names, ["y", "g"]);
+        // The last assignment is described
+        assert_eq!(interface.definitions[0].doc, None);
+        assert_eq!(interface.definitions[1].params, Some(vec!["a".into()]));
+    }
+
+    #[test]
+    fn function_called_before_its_definition() {
+        // Named functions can be called from a lambda even if they are defined later, so the checks pass, but
+        // `k` is called before `h` is assigned and the program fails at runtime. This is intended: the checks
+        // don't follow the calls.
+        let code = "k = f(x) h{x}\nk{1}\nh = f(a) a";
+        let (ast, errors) = Ast::<f64>::parse(code);
+        assert!(errors.is_empty());
+        assert!(ast.interface().is_ok());
+
+        let err = Evaluator::new().run(&ast).unwrap_err();
+        assert_eq!(err.code, ErrCode::UndefinedVariable);
+        let program = Program::compile(&ast).unwrap();
+        let err = Vm::new(&program).run().unwrap_err();
+        assert_eq!(err.code, ErrCode::UndefinedVariable);
+    }
+}
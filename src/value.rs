@@ -0,0 +1,344 @@
+use crate::{
+    common::{CalfErr, ErrCode, Span},
+    lexer::TokenKind,
+    number::CalfNumber,
+};
+use alloc::{borrow::Cow, vec::Vec};
+
+/// Maximum depth of nested function calls.
+pub(crate) const MAX_CALL_DEPTH: usize = 256;
+
+#[derive(Debug, Clone)]
+/// Runtime value. `F` is the representation of a function, that depends on the runtime.
+pub enum Value<T, F> {
+    Number(T),
+    Vector(Vec<T>),
+    Function(F),
+}
+
+/// Number or vector operand of an element-wise operation. Vectors can be borrowed, so the operations only
+/// allocate when they build a new vector.
+pub(crate) enum Operand<'v, T: Clone> {
+    Number(T),
+    Vector(Cow<'v, [T]>),
+}
+
+impl<'v, T: CalfNumber> Operand<'v, T> {
+    /// Take the number or vector of a value. Functions fail with the given message.
+    pub(crate) fn owned<F>(
+        value: Value<T, F>,
+        message: &str,
+        span: &Span,
+    ) -> Result<Self, CalfErr> {
+        match value {
+            Value::Number(n) => Ok(Operand::Number(n)),
+            Value::Vector(v) => Ok(Operand::Vector(Cow::Owned(v))),
+            Value::Function(_) => Err(function_error(message, span)),
+        }
+    }
+
+    /// Borrow the number or vector of a value. Functions fail with the given message.
+    pub(crate) fn borrowed<F>(
+        value: &'v Value<T, F>,
+        message: &str,
+        span: &Span,
+    ) -> Result<Self, CalfErr> {
+        match value {
+            Value::Number(n) => Ok(Operand::Number(*n)),
+            Value::Vector(v) => Ok(Operand::Vector(Cow::Borrowed(v))),
+            Value::Function(_) => Err(function_error(message, span)),
+        }
+    }
+}
+
+/// Messages of the errors of a function used as an operand, an index or a selected branch.
+pub(crate) const OPERAND: &str = "A function can't be an operand";
+pub(crate) const INDEX: &str = "Index must be a number or a vector";
+pub(crate) const SELECT: &str = "A function can't be selected by a vector condition";
+
+pub(crate) fn function_error(message: &str, span: &Span) -> CalfErr {
+    CalfErr {
+        code: ErrCode::TypeMismatch,
+        message: message.into(),
+        span: span.clone(),
+    }
+}
+
+/// Apply a function to all the elements of a vector, reusing it if it's owned.
+fn map<T: Copy>(
+    vector: Cow<'_, [T]>,
+    mut f: impl FnMut(T) -> Result<T, CalfErr>,
+) -> Result<Vec<T>, CalfErr> {
+    match vector {
+        Cow::Owned(mut v) => {
+            for n in v.iter_mut() {
+                *n = f(*n)?;
+            }
+            Ok(v)
+        }
+        Cow::Borrowed(v) => v.iter().map(|n| f(*n)).collect(),
+    }
+}
+
+pub(crate) fn unary<T: CalfNumber, F>(
+    op: TokenKind,
+    operand: Operand<'_, T>,
+) -> Result<Value<T, F>, CalfErr> {
+    let apply = |n: T| -> T {
+        match op {
+            TokenKind::Minus => n.neg(),
+            _ => T::from_bool(!n.is_true()),
+        }
+    };
+    match operand {
+        Operand::Number(n) => Ok(Value::Number(apply(n))),
+        Operand::Vector(v) => Ok(Value::Vector(map(v, |n| Ok(apply(n)))?)),
+    }
+}
+
+/// Result of `&&` or `||` when the left operand is a number that decides it, so the right operand is not
+/// evaluated. Vectors are combined element-wise with the right operand, they never short-circuit.
+pub(crate) fn short_circuit<T: CalfNumber>(op: TokenKind, left: T) -> Option<T> {
+    match op {
+        TokenKind::TwoAnds if !left.is_true() => Some(T::zero()),
+        TokenKind::TwoOrs if left.is_true() => Some(T::one()),
+        _ => None,
+    }
+}
+
+pub(crate) fn apply_binary<T: CalfNumber>(
+    op: TokenKind,
+    a: T,
+    b: T,
+    span: &Span,
+) -> Result<T, CalfErr> {
+    let result = match op {
+        TokenKind::Plus => a.add(b),
+        TokenKind::Minus => a.sub(b),
+        TokenKind::Star => a.mul(b),
+        TokenKind::Slash => a.div(b).ok_or_else(|| CalfErr {
+            code: ErrCode::DivisionByZero,
+            message: "Division by zero".into(),
+            span: span.clone(),
+        })?,
+        TokenKind::Percent => a.rem(b).ok_or_else(|| CalfErr {
+            code: ErrCode::DivisionByZero,
+            message: "Division by zero".into(),
+            span: span.clone(),
+        })?,
+        TokenKind::Caret => a.pow(b).ok_or_else(|| CalfErr {
+            code: ErrCode::DivisionByZero,
+            message: "Zero can't be raised to a negative power".into(),
+            span: span.clone(),
+        })?,
+        // NaN is ignored, unless both operands are NaN
+        TokenKind::LtQuestion if b < a || a.is_nan() => b,
+        TokenKind::GtQuestion if b > a || a.is_nan() => b,
+        TokenKind::LtQuestion | TokenKind::GtQuestion => a,
+        TokenKind::GreaterThan => T::from_bool(a > b),
+        TokenKind::LesserThan => T::from_bool(a < b),
+        TokenKind::GtEqual => T::from_bool(a >= b),
+        TokenKind::LtEqual => T::from_bool(a <= b),
+        TokenKind::TwoEquals => T::from_bool(a == b),
+        TokenKind::NotEqual => T::from_bool(a != b),
+        TokenKind::TwoAnds => T::from_bool(a.is_true() && b.is_true()),
+        TokenKind::TwoOrs => T::from_bool(a.is_true() || b.is_true()),
+        TokenKind::And => a.bit_and(b).ok_or_else(|| bitwise_error::<T>(span))?,
+        TokenKind::Or => a.bit_or(b).ok_or_else(|| bitwise_error::<T>(span))?,
+        _ => {
+            return Err(CalfErr {
+                code: ErrCode::TypeMismatch,
+                message: format!("Unsupported binary operator {:?}", op),
+                span: span.clone(),
+            })
+        }
+    };
+    Ok(result)
+}
+
+pub(crate) fn bitwise_error<T: CalfNumber>(span: &Span) -> CalfErr {
+    CalfErr {
+        code: ErrCode::TypeMismatch,
+        message: format!("Bitwise operators need an integer type, {} is not", T::NAME),
+        span: span.clone(),
+    }
+}
+
+pub(crate) fn binary<T: CalfNumber, F>(
+    op: TokenKind,
+    left: Operand<'_, T>,
+    right: Operand<'_, T>,
+    span: &Span,
+) -> Result<Value<T, F>, CalfErr> {
+    let apply = |a, b| apply_binary(op, a, b, span);
+    let result = match (left, right) {
+        (Operand::Number(a), Operand::Number(b)) => return Ok(Value::Number(apply(a, b)?)),
+        (Operand::Vector(a), Operand::Number(b)) => map(a, |a| apply(a, b))?,
+        (Operand::Number(a), Operand::Vector(b)) => map(b, |b| apply(a, b))?,
+        (Operand::Vector(a), Operand::Vector(b)) => {
+            check_len(a.len(), b.len(), span)?;
+            // The result is written over an owned operand, if any
+            match (a, b) {
+                (Cow::Owned(mut a), b) => {
+                    for (a, b) in a.iter_mut().zip(b.iter()) {
+                        *a = apply(*a, *b)?;
+                    }
+                    a
+                }
+                (a, Cow::Owned(mut b)) => {
+                    for (a, b) in a.iter().zip(b.iter_mut()) {
+                        *b = apply(*a, *b)?;
+                    }
+                    b
+                }
+                (a, b) => a
+                    .iter()
+                    .zip(b.iter())
+                    .map(|(a, b)| apply(*a, *b))
+                    .collect::<Result<_, _>>()?,
+            }
+        }
+    };
+    Ok(Value::Vector(result))
+}
+
+pub(crate) fn select<T: CalfNumber, F>(
+    mask: &[T],
+    then_value: Operand<'_, T>,
+    else_value: Operand<'_, T>,
+    span: &Span,
+) -> Result<Value<T, F>, CalfErr> {
+    let element = |value: &Operand<T>, i: usize| -> Result<T, CalfErr> {
+        match value {
+            Operand::Number(n) => Ok(*n),
+            Operand::Vector(v) => {
+                check_len(mask.len(), v.len(), span)?;
+                Ok(v[i])
+            }
+        }
+    };
+    let mut result = Vec::with_capacity(mask.len());
+    for (i, cond) in mask.iter().enumerate() {
+        if cond.is_true() {
+            result.push(element(&then_value, i)?);
+        } else {
+            result.push(element(&else_value, i)?);
+        }
+    }
+    Ok(Value::Vector(result))
+}
+
+pub(crate) fn indexation<T: CalfNumber, F>(
+    vector: &[T],
+    index: Operand<'_, T>,
+    span: &Span,
+) -> Result<Value<T, F>, CalfErr> {
+    match index {
+        Operand::Number(i) => Ok(Value::Number(element(vector, i, span)?)),
+        // Indexing by a vector gathers all the elements.
+        index => {
+            let mut result = Vec::new();
+            gather(vector, index, &mut result, span)?;
+            Ok(Value::Vector(result))
+        }
+    }
+}
+
+/// Push into `result` the elements of `vector` pointed by `index`, that can be a number or a vector.
+pub(crate) fn gather<T: CalfNumber>(
+    vector: &[T],
+    index: Operand<'_, T>,
+    result: &mut Vec<T>,
+    span: &Span,
+) -> Result<(), CalfErr> {
+    match index {
+        Operand::Number(i) => result.push(element(vector, i, span)?),
+        Operand::Vector(indexes) => {
+            result.reserve(indexes.len());
+            for i in indexes.iter() {
+                result.push(element(vector, *i, span)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn element<T: CalfNumber>(vector: &[T], index: T, span: &Span) -> Result<T, CalfErr> {
+    match index.to_index() {
+        Some(i) if i < vector.len() => Ok(vector[i]),
+        _ => Err(CalfErr {
+            code: ErrCode::OutOfBounds,
+            message: format!(
+                "Index {:?} out of bounds for a vector of length {}",
+                index,
+                vector.len()
+            ),
+            span: span.clone(),
+        }),
+    }
+}
+
+pub(crate) fn range<T: CalfNumber>(
+    init: T,
+    len: T,
+    step: T,
+    span: &Span,
+) -> Result<Vec<T>, CalfErr> {
+    let len = len.to_index().ok_or_else(|| CalfErr {
+        code: ErrCode::InvalidRange,
+        message: "Range size must be a non negative integer".into(),
+        span: span.clone(),
+    })?;
+    let mut vector = Vec::with_capacity(len);
+    let mut value = init;
+    for _ in 0..len {
+        vector.push(value);
+        value = value.add(step);
+    }
+    Ok(vector)
+}
+
+pub(crate) fn slice<T: CalfNumber>(
+    vector: &[T],
+    start: Option<T>,
+    end: Option<T>,
+    span: &Span,
+) -> Result<Vec<T>, CalfErr> {
+    let bound = |n: Option<T>, default: usize| -> Result<usize, CalfErr> {
+        match n {
+            Some(n) => n.to_index().ok_or_else(|| CalfErr {
+                code: ErrCode::OutOfBounds,
+                message: format!("Invalid index {:?}", n),
+                span: span.clone(),
+            }),
+            None => Ok(default),
+        }
+    };
+    let start = bound(start, 0)?;
+    let end = bound(end, vector.len())?;
+    if start > end || end > vector.len() {
+        return Err(CalfErr {
+            code: ErrCode::OutOfBounds,
+            message: format!(
+                "Slice {}..{} out of bounds for a vector of length {}",
+                start,
+                end,
+                vector.len()
+            ),
+            span: span.clone(),
+        });
+    }
+    Ok(vector[start..end].to_vec())
+}
+
+pub(crate) fn check_len(left: usize, right: usize, span: &Span) -> Result<(), CalfErr> {
+    if left != right {
+        Err(CalfErr {
+            code: ErrCode::LengthMismatch,
+            message: format!("Vector length mismatch: {} and {}", left, right),
+            span: span.clone(),
+        })
+    } else {
+        Ok(())
+    }
+}
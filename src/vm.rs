@@ -0,0 +1,496 @@
+use crate::{
+    bytecode::{Instr, Program},
+    common::{CalfErr, ErrCode, Span},
+    host::Registry,
+    number::CalfNumber,
+    value::{
+        binary, gather, indexation, range, select, short_circuit, slice, unary, Operand, Value,
+        INDEX, MAX_CALL_DEPTH, OPERAND, SELECT,
+    },
+};
+use alloc::{borrow::Cow, vec::Vec};
+
+/// Value handled by the virtual machine. Functions are indexes into `Program::functions`.
+pub type VmValue<T> = Value<T, u32>;
+
+/// Value of a global variable or a stack slot.
+#[derive(Debug, Clone)]
+enum Slot<'p, T> {
+    Value(VmValue<T>),
+    /// Vector borrowed from the host, that is only copied when an operation builds a new vector.
+    Slice(&'p [T]),
+}
+
+/// Call frame of a function.
+struct Frame {
+    /// Return address.
+    ret: usize,
+    /// Position in the stack of the first parameter.
+    base: usize,
+}
+
+/// Stack-based virtual machine that executes a compiled program.
+pub struct Vm<'p, T> {
+    program: &'p Program<T>,
+    host: Option<&'p Registry<T>>,
+    globals: Vec<Option<Slot<'p, T>>>,
+    stack: Vec<Slot<'p, T>>,
+    frames: Vec<Frame>,
+    /// Masks of the ternary operators being executed, `None` if the condition was a number.
+    masks: Vec<Option<Slot<'p, T>>>,
+}
+
+impl<'p, T> Vm<'p, T>
+where
+    T: CalfNumber,
+{
+    pub fn new(program: &'p Program<T>) -> Self {
+        Self {
+            program,
+            host: None,
+            globals: vec![None; program.globals.len()],
+            stack: Default::default(),
+            frames: Default::default(),
+            masks: Default::default(),
+        }
+    }
+
+    /// Create a virtual machine for a program compiled with a host registry.
+    pub fn with_registry(program: &'p Program<T>, host: &'p Registry<T>) -> Self {
+        Self {
+            host: Some(host),
+            ..Self::new(program)
+        }
+    }
+
+    /// Define a global variable, usually an input of the program.
+    /// Returns `false` if the program doesn't use this variable.
+    pub fn set(&mut self, name: &str, value: VmValue<T>) -> bool {
+        self.define(name, Slot::Value(value))
+    }
+
+    /// Define a global vector borrowed from the host, without copying it.
+    /// Returns `false` if the program doesn't use this variable.
+    pub fn set_slice(&mut self, name: &str, slice: &'p [T]) -> bool {
+        self.define(name, Slot::Slice(slice))
+    }
+
+    fn define(&mut self, name: &str, value: Slot<'p, T>) -> bool {
+        if let Some(global) = self.program.global(name) {
+            self.globals[global] = Some(value);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Get a global variable. Vectors borrowed with [`Vm::set_slice`], also when assigned to other variables,
+    /// are not values, use [`Vm::get_slice`].
+    pub fn get(&self, name: &str) -> Option<&VmValue<T>> {
+        match self.global(name)? {
+            Slot::Value(value) => Some(value),
+            Slot::Slice(_) => None,
+        }
+    }
+
+    /// Get a global number or vector as a slice, without copying it. Numbers are slices of one element.
+    pub fn get_slice(&self, name: &str) -> Option<&[T]> {
+        match self.global(name)? {
+            Slot::Value(Value::Number(n)) => Some(core::slice::from_ref(n)),
+            Slot::Value(Value::Vector(v)) => Some(v),
+            Slot::Value(Value::Function(_)) => None,
+            Slot::Slice(slice) => Some(slice),
+        }
+    }
+
+    fn global(&self, name: &str) -> Option<&Slot<'p, T>> {
+        self.program
+            .global(name)
+            .and_then(|global| self.globals[global].as_ref())
+    }
+
+    /// Run the program. Returns the value of the last expression statement.
+    pub fn run(&mut self) -> Result<Option<VmValue<T>>, CalfErr> {
+        self.stack.clear();
+        self.frames.clear();
+        self.masks.clear();
+
+        let program = self.program;
+        let mut result = None;
+        let mut ip = 0;
+        loop {
+            let instr = program.code[ip];
+            let span = &program.spans[ip];
+            ip += 1;
+            match instr {
+                Instr::Const(constant) => {
+                    let n = self.program.constants[constant as usize];
+                    self.push(Value::Number(n));
+                }
+                Instr::LoadGlobal(global) => match &self.globals[global as usize] {
+                    Some(slot) => self.stack.push(slot.clone()),
+                    None => {
+                        return Err(CalfErr {
+                            code: ErrCode::UndefinedVariable,
+                            message: format!(
+                                "Undefined symbol '{}'",
+                                self.program.globals[global as usize]
+                            ),
+                            span: span.clone(),
+                        })
+                    }
+                },
+                Instr::StoreGlobal(global) => {
+                    let slot = self.pop();
+                    self.globals[global as usize] = Some(slot);
+                }
+                Instr::LoadLocal(local) => {
+                    let base = self.frames.last().map(|f| f.base).unwrap_or_default();
+                    let slot = self.stack[base + local as usize].clone();
+                    self.stack.push(slot);
+                }
+                Instr::Function(function) => self.push(Value::Function(function)),
+                Instr::Unary(op) => {
+                    let operand = self.pop().operand(OPERAND, span)?;
+                    let value = unary(op, operand)?;
+                    self.push(value);
+                }
+                Instr::Binary(op) => {
+                    let right = self.pop().operand(OPERAND, span)?;
+                    let left = self.pop().operand(OPERAND, span)?;
+                    let value = binary(op, left, right, span)?;
+                    self.push(value);
+                }
+                Instr::ShortCircuit(op, end_addr) => {
+                    if let Some(Slot::Value(Value::Number(left))) = self.stack.last() {
+                        if let Some(result) = short_circuit(op, *left) {
+                            self.pop();
+                            self.push(Value::Number(result));
+                            ip = end_addr as usize;
+                        }
+                    }
+                }
+                Instr::Index => {
+                    let index = self.pop().operand(INDEX, span)?;
+                    let vector = self.pop();
+                    let value = indexation(vector.as_slice(span)?, index, span)?;
+                    self.push(value);
+                }
+                Instr::Vector(len) => {
+                    let start = self.stack.len() - len as usize;
+                    let mut vector = Vec::with_capacity(len as usize);
+                    for slot in self.stack.drain(start..) {
+                        vector.push(number(slot, span)?);
+                    }
+                    self.push(Value::Vector(vector));
+                }
+                Instr::Range { len, step } => {
+                    let step = if step {
+                        number(self.pop(), span)?
+                    } else {
+                        T::zero()
+                    };
+                    let init = number(self.pop(), span)?;
+                    let len = self.program.constants[len as usize];
+                    self.push(Value::Vector(range(init, len, step, span)?));
+                }
+                Instr::Slice { start, end } => {
+                    let end = if end {
+                        Some(number(self.pop(), span)?)
+                    } else {
+                        None
+                    };
+                    let start = if start {
+                        Some(number(self.pop(), span)?)
+                    } else {
+                        None
+                    };
+                    let vector = self.pop();
+                    let value = slice(vector.as_slice(span)?, start, end, span)?;
+                    self.push(Value::Vector(value));
+                }
+                Instr::Gather(len) => {
+                    let start = self.stack.len() - len as usize;
+                    let vector = &self.stack[start - 1];
+                    let vector = vector.as_slice(span)?;
+                    let mut result = Vec::with_capacity(len as usize);
+                    for index in &self.stack[start..] {
+                        gather(vector, index.borrow(INDEX, span)?, &mut result, span)?;
+                    }
+                    // Remove the indexes and the vector
+                    self.stack.truncate(start - 1);
+                    self.push(Value::Vector(result));
+                }
+                Instr::Test(else_addr) => match self.pop() {
+                    Slot::Value(Value::Number(cond)) => {
+                        self.masks.push(None);
+                        if !cond.is_true() {
+                            ip = else_addr as usize;
+                        }
+                    }
+                    mask @ (Slot::Value(Value::Vector(_)) | Slot::Slice(_)) => {
+                        self.masks.push(Some(mask))
+                    }
+                    Slot::Value(Value::Function(_)) => {
+                        return Err(CalfErr {
+                            code: ErrCode::TypeMismatch,
+                            message: "A function can't be used as a condition".into(),
+                            span: span.clone(),
+                        })
+                    }
+                },
+                Instr::Else(end_addr) => {
+                    if let Some(None) = self.masks.last() {
+                        ip = end_addr as usize;
+                    }
+                }
+                Instr::EndTernary => {
+                    if let Some(Some(mask)) = self.masks.pop() {
+                        let else_value = self.pop().operand(SELECT, span)?;
+                        let then_value = self.pop().operand(SELECT, span)?;
+                        let value = select(mask.as_slice(span)?, then_value, else_value, span)?;
+                        self.push(value);
+                    }
+                }
+                Instr::Call(num_args) => {
+                    let base = self.stack.len() - num_args as usize;
+                    let function = match self.stack[base - 1] {
+                        Slot::Value(Value::Function(function)) => {
+                            self.program.functions[function as usize]
+                        }
+                        _ => {
+                            return Err(CalfErr {
+                                code: ErrCode::NotAFunction,
+                                message: "Only functions can be called".into(),
+                                span: span.clone(),
+                            })
+                        }
+                    };
+                    if function.arity != num_args {
+                        return Err(CalfErr {
+                            code: ErrCode::ArityMismatch,
+                            message: format!(
+                                "Function expects {} arguments, got {}",
+                                function.arity, num_args
+                            ),
+                            span: span.clone(),
+                        });
+                    }
+                    if self.frames.len() >= MAX_CALL_DEPTH {
+                        return Err(CalfErr {
+                            code: ErrCode::CallDepth,
+                            message: "Maximum call depth exceeded".into(),
+                            span: span.clone(),
+                        });
+                    }
+                    self.frames.push(Frame { ret: ip, base });
+                    ip = function.entry as usize;
+                }
+                Instr::CallHost { function, args } => {
+                    let function = self
+                        .host
+                        .and_then(|host| host.get(function as usize))
+                        .ok_or_else(|| CalfErr {
+                            code: ErrCode::InvalidProgram,
+                            message: "Host function not found, the program was compiled with a different registry".into(),
+                            span: span.clone(),
+                        })?;
+                    let start = self.stack.len() - args as usize;
+                    let message = function.function_arg();
+                    let operands = self.stack[start..]
+                        .iter()
+                        .map(|slot| slot.borrow(&message, span))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let value = function.call(&operands, span)?;
+                    self.stack.truncate(start);
+                    self.push(value);
+                }
+                Instr::Return => {
+                    let slot = self.pop();
+                    let frame = self.frames.pop().expect("Return outside of a function");
+                    // Remove the arguments and the function
+                    self.stack.truncate(frame.base - 1);
+                    self.stack.push(slot);
+                    ip = frame.ret;
+                }
+                Instr::Result => result = Some(self.pop().into_value()),
+                Instr::Halt => break,
+            }
+        }
+        Ok(result)
+    }
+
+    fn push(&mut self, value: VmValue<T>) {
+        self.stack.push(Slot::Value(value));
+    }
+
+    fn pop(&mut self) -> Slot<'p, T> {
+        self.stack.pop().expect("Stack underflow")
+    }
+}
+
+impl<'p, T: CalfNumber> Slot<'p, T> {
+    /// Take the number or vector of the slot, keeping a borrowed slice borrowed.
+    fn operand(self, message: &str, span: &Span) -> Result<Operand<'p, T>, CalfErr> {
+        match self {
+            Slot::Value(value) => Operand::owned(value, message, span),
+            Slot::Slice(slice) => Ok(Operand::Vector(Cow::Borrowed(slice))),
+        }
+    }
+
+    /// Borrow the number or vector of the slot.
+    fn borrow(&self, message: &str, span: &Span) -> Result<Operand<'_, T>, CalfErr> {
+        match self {
+            Slot::Value(value) => Operand::borrowed(value, message, span),
+            Slot::Slice(slice) => Ok(Operand::Vector(Cow::Borrowed(slice))),
+        }
+    }
+
+    /// Vector of the slot, without copying it.
+    fn as_slice(&self, span: &Span) -> Result<&[T], CalfErr> {
+        match self {
+            Slot::Value(Value::Vector(v)) => Ok(v),
+            Slot::Slice(slice) => Ok(slice),
+            _ => Err(CalfErr {
+                code: ErrCode::TypeMismatch,
+                message: "Only vectors can be indexed".into(),
+                span: span.clone(),
+            }),
+        }
+    }
+
+    /// Value of the slot. Borrowed slices are copied.
+    fn into_value(self) -> VmValue<T> {
+        match self {
+            Slot::Value(value) => value,
+            Slot::Slice(slice) => Value::Vector(slice.to_vec()),
+        }
+    }
+}
+
+fn number<T>(slot: Slot<'_, T>, span: &Span) -> Result<T, CalfErr> {
+    match slot {
+        Slot::Value(Value::Number(n)) => Ok(n),
+        _ => Err(CalfErr {
+            code: ErrCode::TypeMismatch,
+            message: "Expected a number".into(),
+            span: span.clone(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::Ast, eval::Evaluator};
+    use alloc::string::String;
+
+    fn registry<T: CalfNumber>() -> Registry<T> {
+        let mut host = Registry::new();
+        host.register_scalar("sq", 1, |args: &[T]| args[0].mul(args[0]))
+            .register_vector("total", 1, |args: &[&[T]]| {
+                vec![args[0].iter().fold(T::zero(), |sum, n| sum.add(*n))]
+            });
+        host
+    }
+
+    /// Result of a run, where functions are only compared by kind.
+    fn outcome<T: CalfNumber, F>(result: Result<Option<Value<T, F>>, CalfErr>) -> String {
+        match result {
+            Ok(Some(Value::Function(_))) => "function".into(),
+            Ok(Some(Value::Number(n))) => format!("{:?}", n),
+            Ok(Some(Value::Vector(v))) => format!("{:?}", v),
+            Ok(None) => "none".into(),
+            Err(err) => format!("{:?}", err.code),
+        }
+    }
+
+    /// Run a program with the evaluator and the virtual machine, that must produce the same result. The
+    /// input `x` is borrowed by the virtual machine.
+    fn compare<T: CalfNumber>(code: &str, x: &[T]) -> String {
+        let host = registry();
+        let (ast, errors) = Ast::<T>::parse(code);
+        assert!(errors.is_empty(), "{:?} in {}", errors, code);
+        ast.interface_with(&host).unwrap();
+
+        let mut evaluator = Evaluator::with_registry(&host);
+        evaluator.set("x", Value::Vector(x.to_vec()));
+        let expected = outcome(evaluator.run(&ast));
+
+        let program = Program::compile_with(&ast, &host).unwrap();
+        let mut vm = Vm::with_registry(&program, &host);
+        vm.set_slice("x", x);
+        let result = outcome(vm.run());
+        assert_eq!(expected, result, "{}", code);
+        result
+    }
+
+    #[test]
+    fn vm_matches_evaluator() {
+        let x = [3.0, -1.5, 0.0, 8.0];
+        let cases = [
+            ("[1, 2, 3] * 2 + [1, 0, 1]", "[3.0, 4.0, 7.0]"),
+            ("[]", "[]"),
+            ("[1; 4; 2] - 1", "[0.0, 2.0, 4.0, 6.0]"),
+            ("n = 2\n[n * 3; 3]", "[6.0, 6.0, 6.0]"),
+            ("x * 2", "[6.0, -3.0, 0.0, 16.0]"),
+            ("x#1", "-1.5"),
+            ("x#[1..3]", "[-1.5, 0.0]"),
+            ("x#[..2] + x#[2..]", "[3.0, 6.5]"),
+            ("x#[0, 3]", "[3.0, 8.0]"),
+            ("x#[[3, 2], 0]", "[8.0, 0.0, 3.0]"),
+            ("x#[2, 1, 0]", "[0.0, -1.5, 3.0]"),
+            ("x#10", "OutOfBounds"),
+            ("x#[1..9]", "OutOfBounds"),
+            ("[1, 2] + [1]", "LengthMismatch"),
+            ("x > 0 ? x : 0", "[3.0, 0.0, 0.0, 8.0]"),
+            ("x#0 > 2 ? 10 : x", "10.0"),
+            ("x < 0 ? -x : x * 2", "[6.0, 1.5, 0.0, 16.0]"),
+            ("x > 0 ? x > 5 ? 2 : 1 : 0", "[1.0, 0.0, 0.0, 2.0]"),
+            ("0 && x#10", "0.0"),
+            ("1 || x#10", "1.0"),
+            ("y = x\ny#[1, 2]", "[-1.5, 0.0]"),
+            (
+                "fact = f(n) n <= 1 ? 1 : n * fact{n - 1}\nfact{10}",
+                "3628800.0",
+            ),
+            (
+                "fib = f(n) n < 2 ? n : fib{n - 1} + fib{n - 2}\nfib{15}",
+                "610.0",
+            ),
+            ("scale = f(v, k) v * k\nscale{x, 2}#3", "16.0"),
+            ("sq{x}", "[9.0, 2.25, 0.0, 64.0]"),
+            ("sq{3} + total{x}", "[18.5]"),
+            ("total{x#[0, 3]}", "[11.0]"),
+            (
+                "norm = f(v) v / total{v}#0\nnorm{x}#3",
+                "0.8421052631578947",
+            ),
+            ("g = f(a) a\ng", "function"),
+            ("a = 0.0\n1 / -0.0", "-inf"),
+            ("[1 / -0, 1 / -(0), 1 / -0x0]", "[-inf, -inf, -inf]"),
+            ("a = NAN\n[NAN, 1] >? 0", "[0.0, 1.0]"),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(compare(code, &x), expected, "{}", code);
+        }
+    }
+
+    #[test]
+    fn vm_matches_evaluator_with_integers() {
+        let x = [7, -2, 0];
+        let cases = [
+            ("x / 2", "[3, -1, 0]"),
+            ("x / x#2", "DivisionByZero"),
+            ("-9223372036854775808 / -1", "-9223372036854775808"),
+            ("-9223372036854775808 % -1", "0"),
+            ("x & 3 | 8", "[11, 10, 8]"),
+            ("2 ^ [0; 5; 1]", "[1, 2, 4, 8, 16]"),
+            ("x <? 1 >? -1", "[1, -1, 0]"),
+            ("x ? x#[2, 1, 0] : [10; 3]", "[0, -2, 10]"),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(compare::<i64>(code, &x), expected, "{}", code);
+        }
+    }
+}